@@ -0,0 +1,41 @@
+//! A single wrapping `u128` multiply and nothing else, small enough to read
+//! the whole disassembly. Takes no accounts; the instruction data is the two
+//! little-endian operands, and the high word of the product is returned.
+
+#![cfg_attr(target_arch = "bpf", no_std)]
+
+#[cfg(target_arch = "bpf")]
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    unsafe { core::hint::unreachable_unchecked() }
+}
+
+#[cfg(target_arch = "bpf")]
+unsafe extern "C" {
+    fn sol_set_return_data(data: *const u8, len: u64);
+}
+
+#[cfg(not(target_arch = "bpf"))]
+unsafe fn sol_set_return_data(_data: *const u8, _len: u64) {}
+
+/// Instruction data shorter than two operands, or accounts were passed.
+const INVALID_INPUT: u64 = 1;
+
+/// # Safety
+///
+/// `input` must point at the runtime's serialized input.
+#[unsafe(no_mangle)]
+pub unsafe fn entrypoint(input: *mut u8) -> u64 {
+    // With no accounts, the input is the account count (0), the data length
+    // and then the data.
+    let num_accounts = unsafe { *(input as *const u64) };
+    let len = unsafe { *(input.add(8) as *const u64) } as usize;
+    if num_accounts != 0 || len < 32 {
+        return INVALID_INPUT;
+    }
+    let data = unsafe { input.add(16) as *const u128 };
+    let (a, b) = unsafe { (data.read_unaligned(), data.add(1).read_unaligned()) };
+    let high = (a.wrapping_mul(b) >> 64) as u64;
+    unsafe { sol_set_return_data(high.to_le_bytes().as_ptr(), 8) };
+    0
+}
@@ -0,0 +1,290 @@
+//! Access to the runtime's serialized program input.
+
+use crate::ops::MulRecord;
+use crate::Error;
+
+/// Offset of the first operand in the instruction data. The bytes before it
+/// are a header: byte 0 selects the operation, bytes 8..16 hold a
+/// little-endian `u64` parameter whose meaning depends on the operation, and
+/// the rest is reserved except where an operation says otherwise. The header
+/// keeps the operands 16-byte aligned.
+pub const OPERANDS_OFFSET: usize = 16;
+/// Offset of the first operand of operations that read them one byte past
+/// `OPERANDS_OFFSET`, where no 128-bit load can be aligned.
+pub const UNALIGNED_OPERANDS_OFFSET: usize = OPERANDS_OFFSET + 1;
+/// Offset of the element width byte of `WideSum` within the header.
+pub const WIDTH_OFFSET: usize = 1;
+/// Offset of the per-operation `u64` parameter within the header.
+pub const PARAM_OFFSET: usize = 8;
+/// Opcode byte that defers the choice of operation to the last byte of the
+/// program ID, which then holds the `Op` number. Deploying the program under
+/// IDs ending in different bytes gives one program per operation, routed by
+/// address rather than by instruction data.
+pub const PROGRAM_ID_OPCODE: u8 = u8::MAX;
+/// Accounts past this many are skipped over rather than made available.
+pub const MAX_ACCOUNTS: usize = 8;
+
+/// Marks an account entry that is not a duplicate of an earlier one.
+const NON_DUP_MARKER: u8 = u8::MAX;
+/// Bytes of zeroed realloc space the runtime reserves after account data.
+const MAX_PERMITTED_DATA_INCREASE: usize = 10 * 1024;
+
+/// Length-checked view of the instruction data and accounts.
+pub struct Input<'a> {
+    data: &'a [u8],
+    program_id: &'a [u8; 32],
+    accounts: [Option<Account>; MAX_ACCOUNTS],
+}
+
+impl<'a> Input<'a> {
+    /// # Safety
+    ///
+    /// `input` must point at the runtime's serialized input.
+    pub unsafe fn from_raw(input: *mut u8) -> Self {
+        let mut accounts = [None; MAX_ACCOUNTS];
+        let num_accounts = unsafe { *(input as *const u64) } as usize;
+        let mut offset = 8;
+        for index in 0..num_accounts {
+            let entry = unsafe { input.add(offset) };
+            let dup_info = unsafe { *entry };
+            let account = if dup_info == NON_DUP_MARKER {
+                let account = Account { entry };
+                offset += Account::DATA_OFFSET + account.data_len() + MAX_PERMITTED_DATA_INCREASE;
+                offset = (offset + 7) & !7;
+                // rent_epoch
+                offset += 8;
+                Some(account)
+            } else {
+                offset += 8;
+                accounts.get(dup_info as usize).copied().flatten()
+            };
+            if let Some(slot) = accounts.get_mut(index) {
+                *slot = account;
+            }
+        }
+
+        let len = unsafe { *(input.add(offset) as *const u64) } as usize;
+        let data = unsafe { core::slice::from_raw_parts(input.add(offset + 8), len) };
+        // The program ID follows the instruction data.
+        let program_id = unsafe { &*(input.add(offset + 8 + len) as *const [u8; 32]) };
+        Self {
+            data,
+            program_id,
+            accounts,
+        }
+    }
+
+    /// The opcode byte at the start of the header, or the last byte of the
+    /// program ID if that byte is `PROGRAM_ID_OPCODE`.
+    pub fn opcode(&self) -> Result<u8, Error> {
+        match self.data.first().copied().ok_or(Error::InsufficientInput)? {
+            PROGRAM_ID_OPCODE => Ok(self.program_id[31]),
+            opcode => Ok(opcode),
+        }
+    }
+
+    /// The byte at `WIDTH_OFFSET` in the header.
+    pub fn width(&self) -> Result<u8, Error> {
+        self.data
+            .get(WIDTH_OFFSET)
+            .copied()
+            .ok_or(Error::InsufficientInput)
+    }
+
+    /// The per-operation parameter in the header.
+    pub fn param(&self) -> Result<u64, Error> {
+        let bytes = self
+            .data
+            .get(PARAM_OFFSET..OPERANDS_OFFSET)
+            .ok_or(Error::InsufficientInput)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap_or_default()))
+    }
+
+    /// The `index`th 16-byte operand after the header.
+    pub fn operand(&self, index: usize) -> Result<u128, Error> {
+        self.u128_at(OPERANDS_OFFSET + index * 16)
+    }
+
+    /// Length of the instruction data, header included.
+    pub fn data_len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Number of whole 16-byte operands after the header.
+    pub fn operand_count(&self) -> usize {
+        self.data.len().saturating_sub(OPERANDS_OFFSET) / 16
+    }
+
+    /// Every whole 16-byte operand after the header, in order.
+    pub fn operands(&self) -> impl Iterator<Item = u128> + '_ {
+        (0..self.operand_count()).filter_map(|index| self.operand(index).ok())
+    }
+
+    /// `len` bytes at `offset` into the instruction data.
+    pub fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], Error> {
+        let end = offset.checked_add(len).ok_or(Error::InsufficientInput)?;
+        self.data.get(offset..end).ok_or(Error::InsufficientInput)
+    }
+
+    /// The little-endian `u128` at `offset` into the instruction data.
+    pub fn u128_at(&self, offset: usize) -> Result<u128, Error> {
+        read_u128(self.data, offset)
+    }
+
+    /// The little-endian `u128` at `offset` into the instruction data, always
+    /// read with an unaligned load.
+    pub fn unaligned_u128_at(&self, offset: usize) -> Result<u128, Error> {
+        let bytes = self.bytes(offset, 16)?;
+        Ok(unsafe { (bytes.as_ptr() as *const u128).read_unaligned() })
+    }
+
+    /// The `index`th account passed to the instruction.
+    pub fn account(&self, index: usize) -> Result<Account, Error> {
+        self.accounts
+            .get(index)
+            .copied()
+            .flatten()
+            .ok_or(Error::MissingAccount)
+    }
+}
+
+/// A type that can be copied to and from account data as raw bytes, like
+/// `bytemuck::Pod`.
+///
+/// # Safety
+///
+/// Every bit pattern must be a valid value, and the type must have no
+/// padding bytes.
+pub unsafe trait Pod: Copy {}
+
+unsafe impl Pod for MulRecord {}
+
+/// An account in the serialized input. Several `Account`s may refer to the
+/// same memory when the instruction lists an account more than once, so data
+/// is only accessed by copying in or out, never through long-lived references.
+/// Two `Account`s compare equal when they refer to the same entry.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Account {
+    /// Start of the account's (non-duplicate) entry in the input.
+    entry: *mut u8,
+}
+
+impl Account {
+    const DATA_LEN_OFFSET: usize = 80;
+    const DATA_OFFSET: usize = 88;
+
+    pub fn data_len(&self) -> usize {
+        unsafe { *(self.entry.add(Self::DATA_LEN_OFFSET) as *const u64) as usize }
+    }
+
+    /// The byte at `offset` into the account data.
+    pub fn u8_at(&self, offset: usize) -> Result<u8, Error> {
+        let data = unsafe {
+            core::slice::from_raw_parts(self.entry.add(Self::DATA_OFFSET), self.data_len())
+        };
+        data.get(offset).copied().ok_or(Error::InsufficientInput)
+    }
+
+    /// Store `value` at `offset` into the account data.
+    pub fn write_u8(&self, offset: usize, value: u8) -> Result<(), Error> {
+        let data = unsafe {
+            core::slice::from_raw_parts_mut(self.entry.add(Self::DATA_OFFSET), self.data_len())
+        };
+        *data.get_mut(offset).ok_or(Error::InsufficientInput)? = value;
+        Ok(())
+    }
+
+    /// The little-endian `u64` at `offset` into the account data.
+    pub fn u64_at(&self, offset: usize) -> Result<u64, Error> {
+        let data = unsafe {
+            core::slice::from_raw_parts(self.entry.add(Self::DATA_OFFSET), self.data_len())
+        };
+        let end = offset.checked_add(8).ok_or(Error::InsufficientInput)?;
+        let bytes = data.get(offset..end).ok_or(Error::InsufficientInput)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap_or_default()))
+    }
+
+    /// The little-endian `u128` at `offset` into the account data.
+    pub fn u128_at(&self, offset: usize) -> Result<u128, Error> {
+        let data = unsafe {
+            core::slice::from_raw_parts(self.entry.add(Self::DATA_OFFSET), self.data_len())
+        };
+        read_u128(data, offset)
+    }
+
+    /// Copy a `T` out of the start of the account data.
+    pub fn read<T: Pod>(&self) -> Result<T, Error> {
+        let data = unsafe {
+            core::slice::from_raw_parts(self.entry.add(Self::DATA_OFFSET), self.data_len())
+        };
+        let bytes = data
+            .get(..core::mem::size_of::<T>())
+            .ok_or(Error::InsufficientInput)?;
+        let ptr = bytes.as_ptr() as *const T;
+        // The runtime only aligns account data to 8 bytes, less than a `u128`
+        // field needs.
+        if ptr.is_aligned() {
+            Ok(unsafe { ptr.read() })
+        } else {
+            Ok(unsafe { ptr.read_unaligned() })
+        }
+    }
+
+    /// Copy `value` over the start of the account data.
+    pub fn write<T: Pod>(&self, value: &T) -> Result<(), Error> {
+        let data = unsafe {
+            core::slice::from_raw_parts_mut(self.entry.add(Self::DATA_OFFSET), self.data_len())
+        };
+        let bytes = data
+            .get_mut(..core::mem::size_of::<T>())
+            .ok_or(Error::InsufficientInput)?;
+        let ptr = bytes.as_mut_ptr() as *mut T;
+        if ptr.is_aligned() {
+            unsafe { ptr.write(*value) };
+        } else {
+            unsafe { ptr.write_unaligned(*value) };
+        }
+        Ok(())
+    }
+
+    /// Store `value` little-endian at `offset` into the account data.
+    pub fn write_u64(&self, offset: usize, value: u64) -> Result<(), Error> {
+        let data = unsafe {
+            core::slice::from_raw_parts_mut(self.entry.add(Self::DATA_OFFSET), self.data_len())
+        };
+        let end = offset.checked_add(8).ok_or(Error::InsufficientInput)?;
+        let bytes = data.get_mut(offset..end).ok_or(Error::InsufficientInput)?;
+        bytes.copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    /// Store `value` little-endian at `offset` into the account data.
+    pub fn write_u128(&self, offset: usize, value: u128) -> Result<(), Error> {
+        let data = unsafe {
+            core::slice::from_raw_parts_mut(self.entry.add(Self::DATA_OFFSET), self.data_len())
+        };
+        let end = offset.checked_add(16).ok_or(Error::InsufficientInput)?;
+        let bytes = data.get_mut(offset..end).ok_or(Error::InsufficientInput)?;
+        let ptr = bytes.as_mut_ptr() as *mut u128;
+        // Same aligned fast path as `read_u128`.
+        if ptr.is_aligned() {
+            unsafe { ptr.write(value) };
+        } else {
+            unsafe { ptr.write_unaligned(value) };
+        }
+        Ok(())
+    }
+}
+
+/// The little-endian `u128` at `offset` into `bytes`.
+fn read_u128(bytes: &[u8], offset: usize) -> Result<u128, Error> {
+    let end = offset.checked_add(16).ok_or(Error::InsufficientInput)?;
+    let bytes = bytes.get(offset..end).ok_or(Error::InsufficientInput)?;
+    let ptr = bytes.as_ptr() as *const u128;
+    // Keep the aligned fast path a plain 128-bit load.
+    if ptr.is_aligned() {
+        Ok(unsafe { ptr.read() })
+    } else {
+        Ok(unsafe { ptr.read_unaligned() })
+    }
+}
@@ -1,4 +1,15 @@
 #![cfg_attr(target_arch = "bpf", no_std)]
+// A focused build (see the `op-*` features) leaves the other ops' helpers unused.
+#![cfg_attr(
+    not(all(
+        feature = "op-mul",
+        feature = "op-div",
+        feature = "op-mod",
+        feature = "op-add",
+        feature = "op-bitwise"
+    )),
+    allow(dead_code)
+)]
 
 #[cfg(target_arch = "bpf")]
 #[panic_handler]
@@ -6,36 +17,494 @@ fn panic(_: &core::panic::PanicInfo) -> ! {
     unsafe { core::hint::unreachable_unchecked() }
 }
 
+mod input;
+mod ops;
+mod syscalls;
+
+#[cfg(test)]
+mod tests;
+
+use input::Input;
+use ops::Op;
+
+/// Error codes returned from the entrypoint; they surface as
+/// `InstructionError::Custom(code)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u64)]
+pub enum Error {
+    /// The instruction data is too short for the selected operation.
+    InsufficientInput = 1,
+    /// The opcode byte (or an operation's selector in the header) does not
+    /// name a known operation or variant.
+    UnknownOp = 2,
+    /// A modular operation was given a zero modulus.
+    ZeroModulus = 3,
+    /// The operation needs an account the instruction didn't pass.
+    MissingAccount = 4,
+    /// A division was given a zero divisor.
+    DivisionByZero = 5,
+    /// A hex-encoded operand contains a byte that isn't a hex digit.
+    InvalidHex = 6,
+    /// The result doesn't fit in the `u128` the operation returns.
+    Overflow = 7,
+    /// A Montgomery reduction's modulus isn't odd and below 2^64, its `n'`
+    /// doesn't match it, or the value to reduce is out of range.
+    InvalidModulus = 8,
+    /// A recursive operation was asked to recurse past its depth limit.
+    RecursionTooDeep = 9,
+    /// An operation's output account is also one of its inputs, which the
+    /// operation can't compute in place.
+    AliasedAccount = 10,
+}
+
+/// Program entrypoint. Dispatches on the opcode byte at the start of the
+/// instruction data (or on the program ID, see `input::PROGRAM_ID_OPCODE`)
+/// and publishes the operation's `u64` result as
+/// little-endian return data.
+///
+/// # Safety
+///
+/// `i` must point at the runtime's serialized input.
 #[unsafe(no_mangle)]
-pub fn entrypoint(i: *mut u8) -> u64 {
-    let mut a = unsafe { *(i.add(0x0010) as *const u128) };
-    let b = unsafe { *((i.add(0x0010) as *const u128).wrapping_add(1)) };
-    
-    for _ in 0..10000 {
-        // reassign a to avoid multiply being optimized away
-        a = a * b;
+pub unsafe fn entrypoint(i: *mut u8) -> u64 {
+    let input = unsafe { Input::from_raw(i) };
+    match process(&input) {
+        Ok(result) => {
+            syscalls::set_return_data(&result.to_le_bytes());
+            0
+        }
+        Err(error) => error as u64,
     }
-    
-    (a >> 64) as u64
 }
 
-#[cfg(test)]
-mod tests {
-    use mollusk_svm::{Mollusk, result::Check};
-    use solana_instruction::Instruction;
-
-    const PROGRAM_ID: [u8; 32] = [0x02; 32];
-
-    #[test]
-    pub fn test() {
-        let mollusk = Mollusk::new(&PROGRAM_ID.into(), // 
-            "target/bpfel-unknown-none/release/libupstream_u128_test");
-        let input_data : [i128; 2] = [10, 20];
-        let instruction = solana_instruction::Instruction {
-            program_id: PROGRAM_ID.into(),
-            accounts: vec![],
-            data: input_data.iter().flat_map(|x| x.to_le_bytes()).collect(),
-        };
-        mollusk.process_and_validate_instruction(&instruction, &[], &[Check::success()]);
+fn process(input: &Input) -> Result<u64, Error> {
+    let op = Op::from_u8(input.opcode()?).ok_or(Error::UnknownOp)?;
+    match op {
+        #[cfg(feature = "op-mul")]
+        Op::Mul => {
+            let iterations = iterations(input)?;
+            let result = ops::mul_loop(input.operand(0)?, input.operand(1)?, iterations);
+            Ok(ops::high_word(result))
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MixedSignMul => {
+            let iterations = iterations(input)?;
+            let (signed, unsigned) =
+                ops::mixed_sign_mul(input.operand(0)?, input.operand(1)?, iterations);
+            Ok(ops::mixed_sign_word(signed, unsigned))
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulSchoolbook => {
+            let iterations = iterations(input)?;
+            let result = ops::mul_loop_schoolbook(input.operand(0)?, input.operand(1)?, iterations);
+            Ok(ops::high_word(result))
+        }
+        #[cfg(feature = "op-add")]
+        Op::CarryCount => Ok(ops::carry_count(input.operands())),
+        #[cfg(feature = "op-mod")]
+        Op::ModPow => {
+            let modulus = input.operand(2)?;
+            if modulus == 0 {
+                return Err(Error::ZeroModulus);
+            }
+            let result = ops::mod_pow(input.operand(0)?, input.operand(1)?, modulus);
+            Ok(ops::high_word(result))
+        }
+        #[cfg(feature = "op-mul")]
+        Op::CheckTable => check_table(input),
+        #[cfg(feature = "op-mul")]
+        Op::BigMul => big_mul(input),
+        #[cfg(feature = "op-mul")]
+        Op::BitLength => Ok(ops::bit_length(input.operand(0)?, input.operand(1)?)),
+        #[cfg(feature = "op-mul")]
+        Op::MulPopcount => Ok(ops::popcount(input.operand(0)?, input.operand(1)?)),
+        #[cfg(feature = "op-mul")]
+        Op::MulStruct => {
+            let account = input.account(0)?;
+            let mut record: ops::MulRecord = account.read()?;
+            record.multiply();
+            account.write(&record)?;
+            Ok(ops::high_word(record.product))
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulCount => {
+            let count = input.param()?;
+            let output = input.account(0)?;
+            // Counter at 0..8, products at 16..32: check up front rather than
+            // spend the whole count only to fail on the final write.
+            if output.data_len() < 32 {
+                return Err(Error::InsufficientInput);
+            }
+            let products = ops::mul_count(input.operand(0)?, input.operand(1)?, count, |done| {
+                output.write_u64(0, done)
+            })?;
+            output.write_u128(16, products)?;
+            Ok(count)
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulCost => {
+            let first = mul_cost(input.operand(0)?, input.operand(1)?);
+            let second = mul_cost(input.operand(2)?, input.operand(3)?);
+            let output = input.account(0)?;
+            output.write_u64(0, first)?;
+            output.write_u64(8, second)?;
+            Ok(first.abs_diff(second))
+        }
+        #[cfg(feature = "op-mod")]
+        Op::MontgomeryReduce => {
+            let (t, n) = (input.operand(0)?, input.operand(1)?);
+            if n == 0 {
+                return Err(Error::ZeroModulus);
+            }
+            let n_prime = u64::try_from(input.operand(2)?).map_err(|_| Error::InvalidModulus)?;
+            let reduced = ops::montgomery_reduce(t, n, n_prime).ok_or(Error::InvalidModulus)?;
+            input.account(0)?.write_u128(0, reduced as u128)?;
+            Ok(reduced)
+        }
+        #[cfg(feature = "op-mod")]
+        Op::MulMod => {
+            let p = input.operand(2)?;
+            if p == 0 {
+                return Err(Error::ZeroModulus);
+            }
+            let result = ops::mul_mod(input.operand(0)?, input.operand(1)?, p);
+            input.account(0)?.write_u128(0, result)?;
+            Ok(ops::high_word(result))
+        }
+        #[cfg(feature = "op-div")]
+        Op::MulDivWide => {
+            let (a, b, c) = (input.operand(0)?, input.operand(1)?, input.operand(2)?);
+            if c == 0 {
+                return Err(Error::DivisionByZero);
+            }
+            let quotient = ops::mul_div_wide(a, b, c).ok_or(Error::Overflow)?;
+            input.account(0)?.write_u128(0, quotient)?;
+            Ok(ops::high_word(quotient))
+        }
+        #[cfg(feature = "op-add")]
+        Op::WideSum => {
+            let width = input.width()? as usize;
+            if !matches!(width, 8 | 16 | 32) {
+                return Err(Error::UnknownOp);
+            }
+            let count = input.param()? as usize;
+            let mut sum = (0, 0);
+            for index in 0..count {
+                let offset = input::OPERANDS_OFFSET + index * width;
+                let element = match width {
+                    8 => {
+                        let bytes = input.bytes(offset, 8)?;
+                        (
+                            u64::from_le_bytes(bytes.try_into().unwrap_or_default()) as u128,
+                            0,
+                        )
+                    }
+                    16 => (input.u128_at(offset)?, 0),
+                    _ => (input.u128_at(offset)?, input.u128_at(offset + 16)?),
+                };
+                sum = ops::add_wide(sum, element);
+            }
+            let output = input.account(0)?;
+            output.write_u128(0, sum.0)?;
+            output.write_u128(16, sum.1)?;
+            Ok(sum.1 as u64)
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulIf => {
+            let (a, b) = (input.operand(0)?, input.operand(1)?);
+            if input.param()? != 0 {
+                Ok(ops::high_word(a.wrapping_mul(b)))
+            } else {
+                Ok(ops::NOT_TAKEN)
+            }
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulIndex => {
+            let product = input.operand(0)?.wrapping_mul(input.operand(1)?);
+            let account = input.account(0)?;
+            let len = account.data_len() as u64;
+            if len == 0 {
+                return Err(Error::InsufficientInput);
+            }
+            let index = (product as u64 % len) as usize;
+            let byte = account.u8_at(index)?;
+            account.write_u8(index, !byte)?;
+            Ok(byte as u64)
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulRecursive => {
+            let depth = input.param()?;
+            if depth > ops::MAX_RECURSION_DEPTH {
+                return Err(Error::RecursionTooDeep);
+            }
+            Ok(ops::high_word(ops::mul_recursive(
+                input.operand(0)?,
+                input.operand(1)?,
+                depth,
+            )))
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulNested => {
+            let (a, b) = (input.operand(0)?, input.operand(1)?);
+            let (c, d) = (input.operand(2)?, input.operand(3)?);
+            Ok(ops::high_word(
+                a.wrapping_mul(b).wrapping_mul(c).wrapping_mul(d),
+            ))
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulSplit => {
+            let account = input.account(0)?;
+            let low = input.operand(0)? as u64;
+            let high = account.u64_at(0)?;
+            let product = ((high as u128) << 64 | low as u128).wrapping_mul(input.operand(1)?);
+            account.write_u128(16, product)?;
+            Ok(ops::high_word(product))
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulReversed => {
+            let a = input.operand(0)?;
+            Ok(ops::high_word(a.reverse_bits().wrapping_mul(a)))
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulHash => {
+            let len = input.data_len().saturating_sub(input::OPERANDS_OFFSET);
+            let hash = ops::mul_hash(input.bytes(input::OPERANDS_OFFSET, len)?);
+            if let Ok(output) = input.account(0) {
+                output.write_u128(0, hash)?;
+            }
+            Ok(ops::high_word(hash))
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulSignExtend => {
+            let a = input.operand(0)? as u64 as i64;
+            let product = ops::mul_sign_extend(a, input.operand(1)? as i128);
+            Ok(ops::high_word(product as u128))
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulIdentities => Ok(ops::mul_identity_failures(input.operand(0)?)),
+        #[cfg(feature = "op-mul")]
+        Op::MulLaws => {
+            let c = input.operand(2).ok();
+            Ok(ops::mul_law_failures(
+                input.operand(0)?,
+                input.operand(1)?,
+                c,
+            ))
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulCrc => {
+            let product = input.operand(0)?.wrapping_mul(input.operand(1)?);
+            Ok(ops::crc32(&product.to_le_bytes()) as u64)
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulTrace => mul_trace(input),
+        #[cfg(feature = "op-mul")]
+        Op::MulAbove => {
+            let product = input.operand(0)?.wrapping_mul(input.operand(1)?);
+            Ok((product > input.operand(2)?) as u64)
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MaxIdentity => {
+            let holds = ops::max_identity(input.param()?, input.operand(0)?);
+            Ok(holds.ok_or(Error::UnknownOp)? as u64)
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulUnaligned => {
+            let operand = |index: usize| {
+                input.unaligned_u128_at(input::UNALIGNED_OPERANDS_OFFSET + index * 16)
+            };
+            Ok(ops::high_word(operand(0)?.wrapping_mul(operand(1)?)))
+        }
+        #[cfg(feature = "op-mul")]
+        Op::HexMul => {
+            let operand = |index: usize| {
+                let offset = input::OPERANDS_OFFSET + index * ops::HEX_DIGITS;
+                let digits = input.bytes(offset, ops::HEX_DIGITS)?;
+                ops::parse_hex(digits).ok_or(Error::InvalidHex)
+            };
+            Ok(ops::high_word(operand(0)?.wrapping_mul(operand(1)?)))
+        }
+        #[cfg(feature = "op-mul")]
+        Op::DotProduct | Op::DotProductWide => {
+            let n = input.param()? as usize;
+            if input.operand_count() / 2 < n {
+                return Err(Error::InsufficientInput);
+            }
+            let mut operands = input.operands();
+            let pairs = core::iter::from_fn(|| Some((operands.next()?, operands.next()?))).take(n);
+            if op == Op::DotProduct {
+                return Ok(ops::high_word(ops::dot_product(pairs)));
+            }
+            let (low, high) = ops::dot_product_wide(pairs);
+            let output = input.account(0)?;
+            output.write_u128(0, low)?;
+            output.write_u128(16, high)?;
+            Ok(ops::high_word(high))
+        }
+        #[cfg(feature = "op-div")]
+        Op::MulDiv => {
+            let a = input.operand(0)?;
+            let b = input.operand(1)?;
+            if b == 0 {
+                return Err(Error::DivisionByZero);
+            }
+            let product = a.wrapping_mul(b);
+            let output = input.account(0)?;
+            output.write_u128(0, product)?;
+            output.write_u128(16, a / b)?;
+            Ok(ops::high_word(product))
+        }
+        #[cfg(feature = "op-mul")]
+        Op::WideMulLimbs => {
+            let limbs = ops::widening_mul_limbs(input.operand(0)?, input.operand(1)?);
+            let output = input.account(0)?;
+            for (index, limb) in limbs.iter().enumerate() {
+                output.write_u64(index * 8, *limb)?;
+            }
+            Ok(limbs[3])
+        }
+        #[cfg(feature = "op-div")]
+        Op::Gcd => {
+            let gcd = ops::gcd(input.operand(0)?, input.operand(1)?);
+            input.account(0)?.write_u128(0, gcd)?;
+            Ok(ops::high_word(gcd))
+        }
+        #[cfg(feature = "op-mul")]
+        Op::WideMul => {
+            let (low, high) = ops::widening_mul(input.operand(0)?, input.operand(1)?);
+            let output = input.account(0)?;
+            output.write_u128(0, low)?;
+            output.write_u128(16, high)?;
+            Ok(ops::high_word(high))
+        }
+        #[cfg(feature = "op-div")]
+        Op::Div | Op::DivShiftMatch | Op::MulDivIdentity => {
+            let a = input.operand(0)?;
+            let b = input.operand(1)?;
+            if b == 0 {
+                return Err(Error::DivisionByZero);
+            }
+            Ok(match op {
+                Op::Div => ops::high_word(a / b),
+                Op::DivShiftMatch => ops::div_shift_match(a, b) as u64,
+                _ => ops::mul_div_identity(a, b) as u64,
+            })
+        }
+        #[cfg(feature = "op-mul")]
+        Op::MulAccounts => {
+            // Read both operands before storing: the output may alias an input.
+            let a = input.account(0)?.u128_at(0)?;
+            let b = input.account(1)?.u128_at(0)?;
+            let product = a.wrapping_mul(b);
+            input.account(2)?.write_u128(0, product)?;
+            Ok(ops::high_word(product))
+        }
+        #[cfg(feature = "op-bitwise")]
+        Op::And | Op::Or | Op::Xor | Op::Not => binary(input, op),
+        #[cfg(feature = "op-mul")]
+        Op::SaturatingMul => binary(input, op),
+        #[cfg(feature = "op-add")]
+        Op::SaturatingAdd | Op::Average => binary(input, op),
+        // Left out of a focused build by its `op-*` feature.
+        #[allow(unreachable_patterns)]
+        _ => Err(Error::UnknownOp),
+    }
+}
+
+/// Evaluate a two-operand op from `ops::binary`, returning the high word.
+fn binary(input: &Input, op: Op) -> Result<u64, Error> {
+    let result = ops::binary(op, input.operand(0)?, input.operand(1)?).ok_or(Error::UnknownOp)?;
+    Ok(ops::high_word(result))
+}
+
+/// The iteration count in the header, with zero meaning `ITERATIONS`.
+fn iterations(input: &Input) -> Result<u64, Error> {
+    Ok(match input.param()? {
+        0 => ops::ITERATIONS,
+        n => n,
+    })
+}
+
+/// Run the multiply chain, storing each sampled product in the trace account
+/// while it has room.
+fn mul_trace(input: &Input) -> Result<u64, Error> {
+    let trace = input.account(0).ok();
+    let interval = input.operand(2)? as u64;
+    let mut samples = 0;
+    let _ = ops::mul_trace(
+        input.operand(0)?,
+        input.operand(1)?,
+        iterations(input)?,
+        interval,
+        |n, product| {
+            if cfg!(feature = "debug-log") {
+                syscalls::log_64(n, ops::high_word(product), product as u64, 0, 0);
+            }
+            if let Some(trace) = trace {
+                let _ = trace.write_u128(samples as usize * 16, product);
+            }
+            samples += 1;
+        },
+    );
+    Ok(samples)
+}
+
+/// Compute units spent on the wrapping multiply `a * b`. The `black_box`es
+/// keep the multiply between the two meter readings rather than hoisted out
+/// or folded away.
+fn mul_cost(a: u128, b: u128) -> u64 {
+    use core::hint::black_box;
+
+    let before = syscalls::remaining_compute_units();
+    black_box(black_box(a).wrapping_mul(black_box(b)));
+    before.saturating_sub(syscalls::remaining_compute_units())
+}
+
+/// Schoolbook multiply of the `n`-limb numbers in accounts 0 and 1 into the
+/// `2n` limbs of account 2, `n` from the header. Each limb product, the limb
+/// it lands on and the carry in sum to at most `2^128 - 1`, so one `u128`
+/// holds the lot. The output is cleared first, so it can't double as an
+/// input: that is rejected with `AliasedAccount`.
+fn big_mul(input: &Input) -> Result<u64, Error> {
+    let n = input.param()? as usize;
+    let (a, b, product) = (input.account(0)?, input.account(1)?, input.account(2)?);
+    if product == a || product == b {
+        return Err(Error::AliasedAccount);
+    }
+    if product.data_len() / 16 < n {
+        return Err(Error::InsufficientInput);
+    }
+    for limb in 0..2 * n {
+        product.write_u64(limb * 8, 0)?;
+    }
+    for i in 0..n {
+        let a_i = a.u64_at(i * 8)? as u128;
+        let mut carry = 0;
+        for j in 0..n {
+            let at = (i + j) * 8;
+            let sum = a_i * b.u64_at(j * 8)? as u128 + product.u64_at(at)? as u128 + carry;
+            product.write_u64(at, sum as u64)?;
+            carry = sum >> 64;
+        }
+        product.write_u64((i + n) * 8, carry as u64)?;
+    }
+    match n {
+        0 => Ok(0),
+        _ => product.u64_at((2 * n - 1) * 8),
+    }
+}
+
+/// Compare the product of each operand pair against the matching entry of the
+/// table in account 0.
+fn check_table(input: &Input) -> Result<u64, Error> {
+    let table = input.account(0)?;
+    for index in 0..input.operand_count() / 2 {
+        let product = input
+            .operand(2 * index)?
+            .wrapping_mul(input.operand(2 * index + 1)?);
+        if product != table.u128_at(index * 16)? {
+            return Ok(index as u64);
+        }
     }
+    Ok(ops::ALL_MATCH)
 }
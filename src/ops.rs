@@ -0,0 +1,711 @@
+//! The 128-bit operations exercised by the fixture.
+//!
+//! These are plain functions so the test harness can call the very same code
+//! on the host as a reference for what the BPF build returns.
+
+/// Returned by `CheckTable` when every product matched its table entry.
+pub const ALL_MATCH: u64 = u64::MAX;
+
+/// Returned by `MulIf` when its flag is clear and nothing was multiplied.
+pub const NOT_TAKEN: u64 = 0x0bad_f1a9_0bad_f1a9;
+
+/// Bits of the `MulLaws` result: set for each law that failed.
+pub const COMMUTATIVITY_FAILED: u64 = 1;
+pub const ASSOCIATIVITY_FAILED: u64 = 2;
+pub const DISTRIBUTIVITY_FAILED: u64 = 4;
+
+/// Bits of the `MulIdentities` result: set for each identity that failed.
+pub const ZERO_IDENTITY_FAILED: u64 = 1;
+pub const ONE_IDENTITY_FAILED: u64 = 2;
+
+/// Account layout of `MulStruct`, declared the way a program using
+/// `bytemuck` would: `repr(C)` with explicit padding, which puts both
+/// `u128`s after it at 16-byte aligned offsets.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MulRecord {
+    pub a: u128,
+    pub b: u128,
+    pub flag: u64,
+    pub _padding: u64,
+    pub product: u128,
+}
+
+impl MulRecord {
+    /// Store `a * b` in `product`: saturating if `flag` is set, else wrapping.
+    pub fn multiply(&mut self) {
+        self.product = if self.flag != 0 {
+            self.a.saturating_mul(self.b)
+        } else {
+            self.a.wrapping_mul(self.b)
+        };
+    }
+}
+
+/// Length of each operand of `HexMul`: one ASCII hex digit per nibble.
+pub const HEX_DIGITS: usize = 32;
+
+/// Number of multiplies the `Mul` operation chains together when the header
+/// parameter is zero.
+pub const ITERATIONS: u64 = 10000;
+
+/// Operation selected by the first byte of the instruction data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Op {
+    /// `a * b`, chained `param` times (`ITERATIONS` if `param` is zero).
+    Mul = 0,
+    /// `a & b`
+    And = 1,
+    /// `a | b`
+    Or = 2,
+    /// `a ^ b`
+    Xor = 3,
+    /// `!a`; `b` is read but ignored.
+    Not = 4,
+    /// Number of carries out of a chain of wrapping adds over every operand.
+    CarryCount = 5,
+    /// `a^b mod c` by repeated squaring.
+    ModPow = 6,
+    /// Multiply each operand pair once and compare with the table of products
+    /// in account 0, returning the index of the first mismatch or `ALL_MATCH`.
+    CheckTable = 7,
+    /// `a * b` once, with `a` and `b` read from the start of accounts 0 and 1
+    /// and the product written to the start of account 2. Both operands are
+    /// read before the store, so account 2 may alias either input.
+    MulAccounts = 8,
+    /// Number of significant bits in `a * b` (wrapping), 0 for a zero product.
+    BitLength = 9,
+    /// `a * b`, clamped to `u128::MAX` on overflow.
+    SaturatingMul = 10,
+    /// `a + b`, clamped to `u128::MAX` on overflow.
+    SaturatingAdd = 11,
+    /// CRC-32 of the 16 little-endian bytes of `a * b` (wrapping), so every
+    /// bit of the product reaches the return value.
+    MulCrc = 12,
+    /// `Mul`, also sampling the running product every `c` iterations into
+    /// the optional trace account 0 (and the log, with the `debug-log`
+    /// feature). Returns the number of samples taken.
+    MulTrace = 13,
+    /// `a / b`; `b` must be non-zero.
+    Div = 14,
+    /// 1 if `a / b` equals `a >> b.trailing_zeros()`, else 0. Always 1 for a
+    /// power-of-two `b`, where the division should lower to that shift.
+    DivShiftMatch = 15,
+    /// The full 256-bit `a * b`, written to account 0 as its low then high
+    /// `u128`. Returns the high word of the high half.
+    WideMul = 16,
+    /// 1 if `(a * b) / b` (wrapping multiply) recovers `a`, else 0. That
+    /// holds exactly when `a * b` doesn't overflow; `b` must be non-zero.
+    MulDivIdentity = 17,
+    /// `sum(a_i * b_i)` (wrapping) over `param` operand pairs
+    /// `a_0, b_0, a_1, b_1, ...`.
+    DotProduct = 18,
+    /// `a * b` (wrapping), with each operand given as `HEX_DIGITS` ASCII hex
+    /// digits (most significant first, either case) instead of 16 raw bytes.
+    HexMul = 19,
+    /// `a * b` (wrapping) then `a / b`, written to account 0 as two `u128`s.
+    /// Returns the high word of the product; `b` must be non-zero.
+    MulDiv = 20,
+    /// The 256-bit `a * b` as four little-endian `u64` limbs in ascending
+    /// significance, written to account 0. Returns the most significant limb.
+    WideMulLimbs = 21,
+    /// 1 if `u128::MAX` identity number `param` holds with `a` in place of
+    /// `u128::MAX`, else 0; see `max_identity`. Taking the value as an operand
+    /// keeps the check from being folded away at compile time.
+    MaxIdentity = 22,
+    /// 1 if `a * b` (wrapping) exceeds the third operand `t`, else 0. Feeds
+    /// the multiply's result straight into a 128-bit compare.
+    MulAbove = 23,
+    /// `gcd(a, b)` by the Euclidean algorithm, written to account 0 as a
+    /// `u128`. Returns its high word. `gcd(a, 0)` is `a`.
+    Gcd = 24,
+    /// `param` iterations (0 for `ITERATIONS`) of one signed and one unsigned
+    /// multiply of the same bits by `b`; see `mixed_sign_mul`. Returns the
+    /// two chains' high words combined by `mixed_sign_word`.
+    MixedSignMul = 25,
+    /// `Mul` with each multiply done by `schoolbook_mul` instead of the
+    /// native `*`, to compare the compiler's lowering (typically the
+    /// `__multi3` libcall) against inline code for the same product.
+    MulSchoolbook = 26,
+    /// `a * b` (wrapping) with the operands read by unaligned loads from
+    /// `UNALIGNED_OPERANDS_OFFSET` instead of `OPERANDS_OFFSET`. Returns the
+    /// high word.
+    MulUnaligned = 27,
+    /// Number of set bits in `a * b` (wrapping).
+    MulPopcount = 28,
+    /// Checks `a * b == b * a` and, given a third operand `c`,
+    /// `(a * b) * c == a * (b * c)` and `a * (b + c) == a * b + a * c`
+    /// (wrapping); see `mul_law_failures`.
+    /// Returns 0 when every law checked holds.
+    MulLaws = 29,
+    /// The high word of `a * b` (wrapping) if `param` is non-zero, else
+    /// `NOT_TAKEN`. The multiply sits inside the branch rather than in
+    /// straight-line code.
+    MulIf = 30,
+    /// 256-bit wrapping sum of `param` elements packed after the header,
+    /// each `w` bytes with `w` (8, 16 or 32) in the header's width byte.
+    /// Narrower elements are zero-extended. The sum is written to account 0
+    /// as low then high `u128`; returns the low word of the high half.
+    WideSum = 31,
+    /// `a * b / c` (rounded down) with the product kept to all 256 bits, as
+    /// price math needs; see `mul_div_wide`. The quotient is written to
+    /// account 0. Returns its high word; `c` must be non-zero.
+    MulDivWide = 32,
+    /// `a * b` (wrapping) reduced modulo `p`, the third operand; see
+    /// `mul_mod`. The result is written to account 0. Returns its high word;
+    /// `p` must be non-zero.
+    MulMod = 33,
+    /// Compute units one wrapping multiply takes for `a * b` and for
+    /// `c * d`, measured around each with `sol_remaining_compute_units`.
+    /// The two costs are written to account 0 as `u64`s. Returns their
+    /// difference, 0 when the multiply's path doesn't depend on its operands.
+    MulCost = 34,
+    /// `param` independent wrapping multiplies `(a + n) * b`, bumping a
+    /// counter in account 0 after each so progress is visible; see
+    /// `mul_count`. The XOR of the products follows the counter at offset
+    /// 16. Returns the number of multiplies done.
+    MulCount = 35,
+    /// Multiply the `a` and `b` fields of the `MulRecord` in account 0 into
+    /// its `product` field, saturating if its `flag` is non-zero and
+    /// wrapping otherwise. Returns the product's high word.
+    MulStruct = 36,
+    /// One Montgomery reduction of `t` modulo `n` with `R = 2^64`, given
+    /// `n' = -n^-1 mod R` as the third operand; see `montgomery_reduce`.
+    /// The result is written to account 0 and returned whole, as it is
+    /// below `n < 2^64`.
+    MontgomeryReduce = 37,
+    /// Checks `a * 0 == 0` and `a * 1 == a` (wrapping); see
+    /// `mul_identity_failures`. Returns 0 when both hold.
+    MulIdentities = 38,
+    /// `((a * b) * c) * d` (wrapping) over four operands, one expression
+    /// rather than a loop. Returns the high word.
+    MulNested = 39,
+    /// Index account 0's data with the low word of `a * b` (wrapping),
+    /// reduced modulo the data length, and complement the byte there.
+    /// Returns the byte as it was.
+    MulIndex = 40,
+    /// `DotProduct` with each product kept to all 256 bits and summed into
+    /// a 256-bit accumulator; see `dot_product_wide`. The sum is written to
+    /// account 0 as low then high `u128`; returns the high word of its high
+    /// half.
+    DotProductWide = 41,
+    /// `mul_recursive(a, b, param)`, which recurses `param` calls deep
+    /// (at most `MAX_RECURSION_DEPTH`); returns the high word.
+    MulRecursive = 42,
+    /// Multiply the `param`-limb little-endian `u64` arrays in accounts 0
+    /// and 1 by schoolbook, into the `2 * param` limbs of account 2, which
+    /// must be a third account (`AliasedAccount` otherwise). Returns the top
+    /// limb.
+    BigMul = 43,
+    /// The low 64 bits of `a` as an `i64`, sign-extended to `i128` and
+    /// multiplied by `b` as an `i128` (wrapping); returns the high word.
+    MulSignExtend = 44,
+    /// `mul_hash` of every byte after the header; returns the high word and
+    /// also writes the whole hash to account 0 if one is passed.
+    MulHash = 45,
+    /// `a.reverse_bits() * a` (wrapping); returns the high word.
+    MulReversed = 46,
+    /// `(a + b) / 2` rounded down, as `(a & b) + ((a ^ b) >> 1)`, which
+    /// can't overflow.
+    Average = 47,
+    /// `a * b` (wrapping), with `a` put together from the low 64 bits of
+    /// operand 0 and, for its high 64 bits, the first 8 bytes of account
+    /// 0's data. The product is written to bytes 16..32 of account 0;
+    /// returns its high word.
+    MulSplit = 48,
+}
+
+impl Op {
+    /// Every operation taking two `u128` operands and returning a `u128`,
+    /// i.e. those covered by the default vector sweep.
+    #[cfg(test)]
+    pub const BINARY: &'static [Op] = &[
+        Op::Mul,
+        Op::And,
+        Op::Or,
+        Op::Xor,
+        Op::Not,
+        Op::SaturatingMul,
+        Op::SaturatingAdd,
+        Op::Average,
+    ];
+
+    pub fn from_u8(byte: u8) -> Option<Self> {
+        Some(match byte {
+            0 => Op::Mul,
+            1 => Op::And,
+            2 => Op::Or,
+            3 => Op::Xor,
+            4 => Op::Not,
+            5 => Op::CarryCount,
+            6 => Op::ModPow,
+            7 => Op::CheckTable,
+            8 => Op::MulAccounts,
+            9 => Op::BitLength,
+            10 => Op::SaturatingMul,
+            11 => Op::SaturatingAdd,
+            12 => Op::MulCrc,
+            13 => Op::MulTrace,
+            14 => Op::Div,
+            15 => Op::DivShiftMatch,
+            16 => Op::WideMul,
+            17 => Op::MulDivIdentity,
+            18 => Op::DotProduct,
+            19 => Op::HexMul,
+            20 => Op::MulDiv,
+            21 => Op::WideMulLimbs,
+            22 => Op::MaxIdentity,
+            23 => Op::MulAbove,
+            24 => Op::Gcd,
+            25 => Op::MixedSignMul,
+            26 => Op::MulSchoolbook,
+            27 => Op::MulUnaligned,
+            28 => Op::MulPopcount,
+            29 => Op::MulLaws,
+            30 => Op::MulIf,
+            31 => Op::WideSum,
+            32 => Op::MulDivWide,
+            33 => Op::MulMod,
+            34 => Op::MulCost,
+            35 => Op::MulCount,
+            36 => Op::MulStruct,
+            37 => Op::MontgomeryReduce,
+            38 => Op::MulIdentities,
+            39 => Op::MulNested,
+            40 => Op::MulIndex,
+            41 => Op::DotProductWide,
+            42 => Op::MulRecursive,
+            43 => Op::BigMul,
+            44 => Op::MulSignExtend,
+            45 => Op::MulHash,
+            46 => Op::MulReversed,
+            47 => Op::Average,
+            48 => Op::MulSplit,
+            _ => return None,
+        })
+    }
+}
+
+/// Evaluate a two-operand operation, or `None` if `op` takes some other
+/// input shape.
+pub fn binary(op: Op, a: u128, b: u128) -> Option<u128> {
+    Some(match op {
+        Op::Mul => mul_loop(a, b, ITERATIONS),
+        Op::And => a & b,
+        Op::Or => a | b,
+        Op::Xor => a ^ b,
+        Op::Not => !a,
+        Op::SaturatingMul => a.saturating_mul(b),
+        Op::SaturatingAdd => a.saturating_add(b),
+        Op::Average => (a & b) + ((a ^ b) >> 1),
+        _ => return None,
+    })
+}
+
+/// Multiply `a` by `b` `iterations` times with wrapping semantics.
+pub fn mul_loop(mut a: u128, b: u128, iterations: u64) -> u128 {
+    for _ in 0..iterations {
+        // reassign a to avoid multiply being optimized away
+        a = a.wrapping_mul(b);
+    }
+    a
+}
+
+/// Two multiply chains from `a`, interleaved in one loop: `i128` and `u128`
+/// multiplies by `b`, each followed by halving the product. A wrapping
+/// product has the same bits either way, but the halving is arithmetic in
+/// one chain and logical in the other, so once a product has its sign bit
+/// set the chains diverge and a signed/unsigned mixup shows in the result.
+pub fn mixed_sign_mul(a: u128, b: u128, iterations: u64) -> (i128, u128) {
+    let (mut signed, mut unsigned) = (a as i128, a);
+    for _ in 0..iterations {
+        signed = signed.wrapping_mul(b as i128) >> 1;
+        unsigned = unsigned.wrapping_mul(b) >> 1;
+    }
+    (signed, unsigned)
+}
+
+/// The high words of both `mixed_sign_mul` chains in one `u64`, one rotated
+/// so equal words don't cancel.
+pub fn mixed_sign_word(signed: i128, unsigned: u128) -> u64 {
+    high_word(signed as u128) ^ high_word(unsigned).rotate_left(32)
+}
+
+/// `mul_loop`, multiplying with `schoolbook_mul`.
+pub fn mul_loop_schoolbook(mut a: u128, b: u128, iterations: u64) -> u128 {
+    for _ in 0..iterations {
+        a = schoolbook_mul(a, b);
+    }
+    a
+}
+
+/// The wrapping product `a * b` from 64-bit limbs, with every multiply a
+/// `u64` one so no 128-bit multiply is left for the compiler to lower.
+#[inline(always)]
+pub fn schoolbook_mul(a: u128, b: u128) -> u128 {
+    let (a_lo, a_hi) = (a as u64, (a >> 64) as u64);
+    let (b_lo, b_hi) = (b as u64, (b >> 64) as u64);
+    let (low, carry) = mul_u64_wide(a_lo, b_lo);
+    // The cross terms only reach the high limb, so their own high halves
+    // (and `a_hi * b_hi`) wrap out of the result.
+    let high = carry
+        .wrapping_add(a_lo.wrapping_mul(b_hi))
+        .wrapping_add(a_hi.wrapping_mul(b_lo));
+    (high as u128) << 64 | low as u128
+}
+
+/// `a * b` as `(low, high)` 64-bit halves, from 32-bit pieces.
+#[inline(always)]
+fn mul_u64_wide(a: u64, b: u64) -> (u64, u64) {
+    let (a_lo, a_hi) = (a & 0xffff_ffff, a >> 32);
+    let (b_lo, b_hi) = (b & 0xffff_ffff, b >> 32);
+    let lo_lo = a_lo * b_lo;
+    let hi_lo = a_hi * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let hi_hi = a_hi * b_hi;
+    // Each term is below 2^32, so the sum fits with room for its carry.
+    let middle = (lo_lo >> 32) + (hi_lo & 0xffff_ffff) + (lo_hi & 0xffff_ffff);
+    let low = middle << 32 | (lo_lo & 0xffff_ffff);
+    let high = hi_hi + (hi_lo >> 32) + (lo_hi >> 32) + (middle >> 32);
+    (low, high)
+}
+
+/// `mul_loop`, calling `sample(n, a)` with the running product after every
+/// `interval`th iteration `n`. An `interval` of zero takes no samples.
+pub fn mul_trace(
+    mut a: u128,
+    b: u128,
+    iterations: u64,
+    interval: u64,
+    mut sample: impl FnMut(u64, u128),
+) -> u128 {
+    for n in 1..=iterations {
+        a = a.wrapping_mul(b);
+        if interval != 0 && n % interval == 0 {
+            sample(n, a);
+        }
+    }
+    a
+}
+
+/// XOR of the wrapping products `(a + n) * b` for `n` in `0..count`, calling
+/// `done(n + 1)` after each and stopping at the first error it returns. No
+/// product feeds the next, unlike `mul_loop`.
+pub fn mul_count<E>(
+    a: u128,
+    b: u128,
+    count: u64,
+    mut done: impl FnMut(u64) -> Result<(), E>,
+) -> Result<u128, E> {
+    let mut products = 0;
+    for n in 0..count {
+        products ^= a.wrapping_add(n as u128).wrapping_mul(b);
+        done(n + 1)?;
+    }
+    Ok(products)
+}
+
+/// Parse hex digits, most significant first, into a `u128`. `None` if any
+/// byte isn't a hex digit or there are more than `HEX_DIGITS` of them.
+pub fn parse_hex(digits: &[u8]) -> Option<u128> {
+    if digits.len() > HEX_DIGITS {
+        return None;
+    }
+    let mut value = 0u128;
+    for &digit in digits {
+        let nibble = match digit {
+            b'0'..=b'9' => digit - b'0',
+            b'a'..=b'f' => digit - b'a' + 10,
+            b'A'..=b'F' => digit - b'A' + 10,
+            _ => return None,
+        };
+        value = value << 4 | nibble as u128;
+    }
+    Some(value)
+}
+
+/// Deepest `mul_recursive` the program runs. Each call takes a stack frame,
+/// and the runtime allows 64 of them in all.
+pub const MAX_RECURSION_DEPTH: u64 = 32;
+
+/// `a * b` taken `depth` calls deep, with `b + 1` passed to each deeper call,
+/// then on the way back out each call's result multiplied by its `b` and
+/// XORed with its `a`. The work after the recursive call keeps it from being
+/// turned into a loop, so every `u128` is passed in and returned through a
+/// real call.
+#[inline(never)]
+pub fn mul_recursive(a: u128, b: u128, depth: u64) -> u128 {
+    if depth == 0 {
+        return a;
+    }
+    let inner = mul_recursive(a.wrapping_mul(b), b.wrapping_add(1), depth - 1);
+    inner.wrapping_mul(b) ^ a
+}
+
+/// Starting value of `mul_hash`: the 128-bit FNV offset basis.
+pub const HASH_OFFSET_BASIS: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+/// Multiplier of `mul_hash`: the 128-bit FNV prime, `2^88 + 2^8 + 0x3b`.
+pub const HASH_PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013b;
+
+/// Multiplicative hash of `bytes`: starting from `HASH_OFFSET_BASIS`, each
+/// byte multiplies the running hash by `HASH_PRIME` and is then added in,
+/// both wrapping. One multiply per byte, each depending on the last.
+pub fn mul_hash(bytes: &[u8]) -> u128 {
+    bytes.iter().fold(HASH_OFFSET_BASIS, |hash, &byte| {
+        hash.wrapping_mul(HASH_PRIME).wrapping_add(byte as u128)
+    })
+}
+
+/// Number of identities `max_identity` knows.
+#[cfg(test)]
+pub const MAX_IDENTITIES: u64 = 6;
+
+/// Whether identity `index` of `u128::MAX` holds with `max` in its place, or
+/// `None` for an unknown index.
+pub fn max_identity(index: u64, max: u128) -> Option<bool> {
+    Some(match index {
+        0 => max.wrapping_mul(1) == max,
+        1 => max.wrapping_add(1) == 0,
+        2 => max.checked_div(max) == Some(1),
+        3 => max.checked_rem(max) == Some(0),
+        4 => max.wrapping_mul(max) == 1,
+        5 => max.wrapping_mul(2) == max.wrapping_sub(1),
+        _ => return None,
+    })
+}
+
+/// `ZERO_IDENTITY_FAILED` and `ONE_IDENTITY_FAILED` for whichever of
+/// `a * 0 == 0` and `a * 1 == a` fail. The `0` and `1` go through
+/// `black_box` so the multiplies aren't folded away.
+pub fn mul_identity_failures(a: u128) -> u64 {
+    use core::hint::black_box;
+    let mut failed = 0;
+    if a.wrapping_mul(black_box(0)) != 0 {
+        failed |= ZERO_IDENTITY_FAILED;
+    }
+    if a.wrapping_mul(black_box(1)) != a {
+        failed |= ONE_IDENTITY_FAILED;
+    }
+    failed
+}
+
+/// Wrapping sum of the wrapping products of `pairs`.
+pub fn dot_product(pairs: impl IntoIterator<Item = (u128, u128)>) -> u128 {
+    let mut acc = 0u128;
+    for (a, b) in pairs {
+        acc = acc.wrapping_add(a.wrapping_mul(b));
+    }
+    acc
+}
+
+/// Sum of the full 256-bit products of `pairs`, as `(low, high)` halves
+/// with the carry out of `low` propagated into `high`. Wraps only past 256
+/// bits.
+pub fn dot_product_wide(pairs: impl IntoIterator<Item = (u128, u128)>) -> (u128, u128) {
+    let mut acc = (0, 0);
+    for (a, b) in pairs {
+        acc = add_wide(acc, widening_mul(a, b));
+    }
+    acc
+}
+
+/// Sum `values` with `overflowing_add`, counting how often the 128-bit
+/// accumulator carried out.
+pub fn carry_count(values: impl IntoIterator<Item = u128>) -> u64 {
+    let mut acc = 0u128;
+    let mut carries = 0;
+    for value in values {
+        let (sum, carry) = acc.overflowing_add(value);
+        acc = sum;
+        carries += carry as u64;
+    }
+    carries
+}
+
+/// `a * b / c` rounded down, from the full 256-bit product, or `None` if the
+/// quotient needs more than 128 bits. `c` must be non-zero.
+pub fn mul_div_wide(a: u128, b: u128, c: u128) -> Option<u128> {
+    let (low, high) = widening_mul(a, b);
+    if high >= c {
+        return None;
+    }
+    // Long division of `high:low` by `c`, one bit of `low` at a time. The
+    // remainder stays below `c`, so doubling it overflows 128 bits by at
+    // most the one bit held in `carry`.
+    let (mut remainder, mut quotient) = (high, 0u128);
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = remainder << 1 | (low >> bit) & 1;
+        if carry == 1 || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            quotient |= 1 << bit;
+        }
+    }
+    Some(quotient)
+}
+
+/// `(low, high) + (x_low, x_high)` as 256-bit numbers, wrapping.
+pub fn add_wide((low, high): (u128, u128), (x_low, x_high): (u128, u128)) -> (u128, u128) {
+    let (low, carry) = low.overflowing_add(x_low);
+    (low, high.wrapping_add(x_high).wrapping_add(carry as u128))
+}
+
+/// The 256-bit product `a * b` as `(low, high)`, by schoolbook
+/// multiplication of the 64-bit halves.
+pub fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+    let (a_lo, a_hi) = (a as u64 as u128, a >> 64);
+    let (b_lo, b_hi) = (b as u64 as u128, b >> 64);
+
+    let lo_lo = a_lo * b_lo;
+    let hi_lo = a_hi * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let hi_hi = a_hi * b_hi;
+
+    // Sum the middle column; each term fits in 64 bits, so this can't overflow.
+    let middle = (lo_lo >> 64) + (hi_lo as u64 as u128) + (lo_hi as u64 as u128);
+    let low = (middle << 64) | (lo_lo as u64 as u128);
+    let high = hi_hi + (hi_lo >> 64) + (lo_hi >> 64) + (middle >> 64);
+    (low, high)
+}
+
+/// The 256-bit product `a * b` as `u64` limbs, least significant first.
+pub fn widening_mul_limbs(a: u128, b: u128) -> [u64; 4] {
+    let (low, high) = widening_mul(a, b);
+    [
+        low as u64,
+        (low >> 64) as u64,
+        high as u64,
+        (high >> 64) as u64,
+    ]
+}
+
+/// Greatest common divisor of `a` and `b`, looping on the remainder so each
+/// step's `%` feeds the next step's compare.
+pub fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Whether dividing the wrapping product `a * b` by `b` gives back `a`.
+/// `b` must be non-zero.
+pub fn mul_div_identity(a: u128, b: u128) -> bool {
+    a.wrapping_mul(b) / b == a
+}
+
+/// Whether dividing by `b` agrees with shifting right by its trailing zeros.
+/// `b` must be non-zero.
+pub fn div_shift_match(a: u128, b: u128) -> bool {
+    a / b == a >> b.trailing_zeros()
+}
+
+/// `base^exp mod modulus` by repeated squaring, reducing each wrapping
+/// product with `%`. `modulus` must be non-zero.
+pub fn mod_pow(base: u128, mut exp: u128, modulus: u128) -> u128 {
+    let mut result = 1 % modulus;
+    let mut base = base % modulus;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = result.wrapping_mul(base) % modulus;
+        }
+        base = base.wrapping_mul(base) % modulus;
+        exp >>= 1;
+    }
+    result
+}
+
+/// `a * b mod p` of the wrapping product, the multiply feeding straight
+/// into the remainder. `p` must be non-zero.
+pub fn mul_mod(a: u128, b: u128, p: u128) -> u128 {
+    a.wrapping_mul(b) % p
+}
+
+/// `t * R^-1 mod n` for `R = 2^64`, by Montgomery's REDC: `m = t * n' mod
+/// R`, then `(t + m * n) / R`, less `n` once if that is still `n` or more.
+/// `None` unless `n` is odd and below `R`, `n_prime` is `-n^-1 mod R`, and
+/// `t < n * R`.
+pub fn montgomery_reduce(t: u128, n: u128, n_prime: u64) -> Option<u64> {
+    if n & 1 == 0 || n >> 64 != 0 || t >= n << 64 {
+        return None;
+    }
+    let n = n as u64;
+    if n.wrapping_mul(n_prime) != u64::MAX {
+        return None;
+    }
+    let m = (t as u64).wrapping_mul(n_prime);
+    // `t + m * n` is a multiple of `R` below `2 * n * R`, so it can carry
+    // out of 128 bits; the carry is bit 64 of the quotient.
+    let (sum, carry) = t.overflowing_add(m as u128 * n as u128);
+    let reduced = (sum >> 64) | (carry as u128) << 64;
+    let reduced = if reduced >= n as u128 {
+        reduced - n as u128
+    } else {
+        reduced
+    };
+    Some(reduced as u64)
+}
+
+/// `a` promoted to `i128` times `b`, wrapping. A negative `a` has to fill
+/// the upper 64 bits of the promoted value with ones.
+pub fn mul_sign_extend(a: i64, b: i128) -> i128 {
+    (a as i128).wrapping_mul(b)
+}
+
+/// Bit length of the wrapping product `a * b`.
+pub fn bit_length(a: u128, b: u128) -> u64 {
+    (u128::BITS - a.wrapping_mul(b).leading_zeros()) as u64
+}
+
+/// `COMMUTATIVITY_FAILED`, `ASSOCIATIVITY_FAILED` and `DISTRIBUTIVITY_FAILED`
+/// for whichever of those laws of wrapping `*` (and, for the last, of `*`
+/// over wrapping `+`) fail for `a`, `b` and, if given, `c`. The compiler
+/// knows these laws and would fold each check to `true`, so the operands of
+/// one side go through `black_box` to make it compute both.
+pub fn mul_law_failures(a: u128, b: u128, c: Option<u128>) -> u64 {
+    use core::hint::black_box;
+    let mut failed = 0;
+    if a.wrapping_mul(b) != black_box(b).wrapping_mul(black_box(a)) {
+        failed |= COMMUTATIVITY_FAILED;
+    }
+    if let Some(c) = c {
+        let left = a.wrapping_mul(b).wrapping_mul(c);
+        let right = black_box(a).wrapping_mul(black_box(b).wrapping_mul(black_box(c)));
+        if left != right {
+            failed |= ASSOCIATIVITY_FAILED;
+        }
+        let left = a.wrapping_mul(b.wrapping_add(c));
+        let right = black_box(a)
+            .wrapping_mul(black_box(b))
+            .wrapping_add(black_box(a).wrapping_mul(black_box(c)));
+        if left != right {
+            failed |= DISTRIBUTIVITY_FAILED;
+        }
+    }
+    failed
+}
+
+/// Set bits in the wrapping product `a * b`.
+pub fn popcount(a: u128, b: u128) -> u64 {
+    a.wrapping_mul(b).count_ones() as u64
+}
+
+/// CRC-32 (IEEE 802.3, reflected, as used by zlib) of `bytes`. Bitwise
+/// rather than table-driven to keep the program small.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// The high 64 bits of `x`, which is what most operations return.
+pub fn high_word(x: u128) -> u64 {
+    (x >> 64) as u64
+}
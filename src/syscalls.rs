@@ -0,0 +1,37 @@
+//! Solana syscalls used by the fixture.
+//!
+//! On BPF these resolve against the runtime; host builds (used only to compile
+//! the test harness) get no-op stand-ins so the crate still links.
+
+#[cfg(target_arch = "bpf")]
+mod imp {
+    unsafe extern "C" {
+        pub fn sol_set_return_data(data: *const u8, len: u64);
+        pub fn sol_log_64_(arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64);
+        pub fn sol_remaining_compute_units() -> u64;
+    }
+}
+
+#[cfg(not(target_arch = "bpf"))]
+mod imp {
+    pub unsafe fn sol_set_return_data(_data: *const u8, _len: u64) {}
+    pub unsafe fn sol_log_64_(_arg1: u64, _arg2: u64, _arg3: u64, _arg4: u64, _arg5: u64) {}
+    pub unsafe fn sol_remaining_compute_units() -> u64 {
+        0
+    }
+}
+
+/// Publish `data` as the instruction's return data.
+pub fn set_return_data(data: &[u8]) {
+    unsafe { imp::sol_set_return_data(data.as_ptr(), data.len() as u64) }
+}
+
+/// Log up to five `u64`s to the program log.
+pub fn log_64(arg1: u64, arg2: u64, arg3: u64, arg4: u64, arg5: u64) {
+    unsafe { imp::sol_log_64_(arg1, arg2, arg3, arg4, arg5) }
+}
+
+/// Compute units left in the instruction's budget.
+pub fn remaining_compute_units() -> u64 {
+    unsafe { imp::sol_remaining_compute_units() }
+}
@@ -0,0 +1,2786 @@
+use crate::input::{
+    OPERANDS_OFFSET, PARAM_OFFSET, PROGRAM_ID_OPCODE, UNALIGNED_OPERANDS_OFFSET, WIDTH_OFFSET,
+};
+use crate::ops::{self, Op};
+use crate::Error;
+use mollusk_svm::{
+    result::{Check, ProgramResult},
+    Mollusk,
+};
+use solana_account::Account;
+use solana_instruction::{error::InstructionError, AccountMeta, Instruction};
+use solana_pubkey::Pubkey;
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "program-test")]
+mod program_test;
+mod report;
+
+use report::{CU_REPORT_PREFIX, SMOKE_PREFIX};
+
+const PROGRAM_ID: [u8; 32] = [0x02; 32];
+const PROGRAM_PATH: &str = "target/bpfel-unknown-none/release/libupstream_u128_test";
+/// Program ID the standalone `programs/mul` is loaded under.
+const MUL_PROGRAM_ID: [u8; 32] = [0x03; 32];
+const TABLE_KEY: [u8; 32] = [0x10; 32];
+const LHS_KEY: [u8; 32] = [0x11; 32];
+const RHS_KEY: [u8; 32] = [0x12; 32];
+const OUT_KEY: [u8; 32] = [0x13; 32];
+const TRACE_KEY: [u8; 32] = [0x14; 32];
+const ACCOUNT_LAMPORTS: u64 = 1_000_000_000;
+/// Owner of every `data_account`. A constant so `strict_account_check` can
+/// lend it to the `Check` it returns.
+const PROGRAM_OWNER: Pubkey = Pubkey::new_from_array(PROGRAM_ID);
+/// An entrypoint with the multiply loop folded away is a handful of
+/// loads and a return; anything this long still contains the loop.
+const MIN_ENTRYPOINT_INSNS: usize = 16;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+/// Failing vectors of a sweep that get a reproducer written for them.
+const MAX_REPRODUCERS: usize = 10;
+const REPRO_DIR: &str = "target/u128-repro";
+/// Largest program account the loader accepts (`MAX_PERMITTED_DATA_LENGTH`).
+const LOADER_LIMIT_BYTES: u64 = 10 * 1024 * 1024;
+/// Fraction of the size budget past which `program_fits_loader_limit` warns.
+const SIZE_WARN_PERCENT: u64 = 80;
+/// The runtime's per-transaction compute unit cap.
+const MAX_COMPUTE_UNITS: u64 = 1_400_000;
+/// Seed used by `seeded_operands_match_host` when `U128_SEED` is unset.
+const DEFAULT_SEED: u64 = 0x5eed_0128;
+const DEFAULT_VECTORS: usize = 16;
+/// Both operands of `exhaustive_small_products` range over `0..EXHAUSTIVE_RANGE`;
+/// 256 keeps its 65536 runs down to a few seconds.
+const EXHAUSTIVE_RANGE: u128 = 256;
+/// Times `repeated_runs_are_identical` runs each instruction.
+const REPEATED_RUNS: usize = 100;
+
+/// Running count of a long sweep, redrawn on stderr a few times a second so
+/// a slow run doesn't look hung. Silent unless stderr is a terminal, to keep
+/// CI logs clean.
+struct Progress {
+    label: &'static str,
+    total: usize,
+    run: usize,
+    failures: Vec<Failure>,
+    enabled: bool,
+    last_draw: Instant,
+}
+
+impl Progress {
+    fn new(label: &'static str, total: usize) -> Self {
+        Self {
+            label,
+            total,
+            run: 0,
+            failures: Vec::new(),
+            enabled: std::io::stderr().is_terminal(),
+            last_draw: Instant::now(),
+        }
+    }
+
+    /// Record one vector, with what went wrong if it failed.
+    fn record(&mut self, failure: Option<Failure>) {
+        self.run += 1;
+        self.failures.extend(failure);
+        let finished = self.run == self.total;
+        if self.enabled && (finished || self.last_draw.elapsed() >= PROGRESS_INTERVAL) {
+            let passed = self.run - self.failures.len();
+            eprint!(
+                "\r{}: {}/{} run, {passed} passed, {} failed",
+                self.label,
+                self.run,
+                self.total,
+                self.failures.len()
+            );
+            if finished {
+                eprintln!();
+            }
+            self.last_draw = Instant::now();
+        }
+    }
+
+    /// Fail the test if any vector failed, listing the first few and writing
+    /// a reproducer test for each of them.
+    fn finish(self) {
+        if self.failures.is_empty() {
+            return;
+        }
+        let first = &self.failures[..self.failures.len().min(MAX_REPRODUCERS)];
+        let name = self.label.replace(' ', "_");
+        let reproducers: Vec<String> = first
+            .iter()
+            .enumerate()
+            .map(|(i, failure)| failure.reproducer(&format!("repro_{name}_{i}")))
+            .collect();
+        let path = format!("{REPRO_DIR}/{name}.rs");
+        std::fs::create_dir_all(REPRO_DIR).unwrap();
+        std::fs::write(&path, reproducers.join("\n")).unwrap();
+        println!("reproducers written to {path}; paste them into src/tests.rs:");
+        println!("{}", reproducers.join("\n"));
+
+        let listed: Vec<String> = first.iter().map(Failure::to_string).collect();
+        panic!(
+            "{}: {} of {} vectors failed, first ones:\n{}\nreproducers: {path}",
+            self.label,
+            self.failures.len(),
+            self.run,
+            listed.join("\n")
+        );
+    }
+}
+
+/// Everything a test may want to assert about one run of the program.
+struct RunResult {
+    result: ProgramResult,
+    /// The `u64` the program returned, or `None` unless its return data was
+    /// exactly 8 bytes
+    return_value: Option<u64>,
+    compute_units: u64,
+    /// Messages logged during the run, if the `Mollusk` has a logger
+    logs: Vec<String>,
+    /// Data of the `OUT_KEY` account after the run, if it was passed in
+    output_account: Option<Vec<u8>>,
+}
+
+impl RunResult {
+    /// Panic unless the run succeeded.
+    fn expect_success(self) -> Self {
+        assert!(
+            self.result.is_ok(),
+            "run failed with {:?}, logs:\n{}",
+            self.result,
+            self.logs.join("\n")
+        );
+        self
+    }
+
+    /// `u128` number `index` of the output account.
+    fn output_u128(&self, index: usize) -> u128 {
+        let data = self.output_account.as_ref().expect("no output account");
+        let bytes = &data[index * 16..(index + 1) * 16];
+        u128::from_le_bytes(bytes.try_into().unwrap())
+    }
+}
+
+/// Run `instruction` against `accounts`.
+fn run(mollusk: &Mollusk, instruction: &Instruction, accounts: &[(Pubkey, Account)]) -> RunResult {
+    let logged_before = mollusk
+        .logger
+        .as_ref()
+        .map_or(0, |logger| logger.borrow().get_recorded_content().len());
+    let result = mollusk.process_instruction(instruction, accounts);
+    let logs = mollusk.logger.as_ref().map_or_else(Vec::new, |logger| {
+        logger.borrow().get_recorded_content()[logged_before..].to_vec()
+    });
+    RunResult {
+        return_value: result
+            .return_data
+            .as_slice()
+            .try_into()
+            .ok()
+            .map(u64::from_le_bytes),
+        compute_units: result.compute_units_consumed,
+        logs,
+        output_account: result
+            .get_account(&OUT_KEY.into())
+            .map(|account| account.data.clone()),
+        result: result.program_result,
+    }
+}
+
+/// A vector whose run didn't succeed with the expected return data.
+struct Failure {
+    op: Op,
+    param: u64,
+    operands: Vec<u128>,
+    expected: u64,
+    /// The program result and return data actually seen
+    actual: String,
+}
+
+impl Failure {
+    /// A standalone test running just this vector.
+    fn reproducer(&self, name: &str) -> String {
+        let operands: Vec<String> = self.operands.iter().map(|x| format!("{x:#x}")).collect();
+        format!(
+            "#[test]\nfn {name}() {{\n    \
+             let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());\n    \
+             assert_op_param(&mollusk, Op::{:?}, {}, &[{}], {:#x});\n}}\n",
+            self.op,
+            self.param,
+            operands.join(", "),
+            self.expected
+        )
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} param {} operands {:#x?}: expected {:#x}, got {}",
+            self.op, self.param, self.operands, self.expected, self.actual
+        )
+    }
+}
+
+/// SplitMix64. Hand-rolled rather than pulled from `rand` so a given seed
+/// yields the same operands regardless of dependency versions.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn next_u128(&mut self) -> u128 {
+        (self.next_u64() as u128) << 64 | self.next_u64() as u128
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{name} is not a valid value: {value}")),
+        Err(_) => default,
+    }
+}
+
+/// Where mollusk finds the fixture program (without the `.so`): the file
+/// named by `U128_FIXTURE_SO` if set, such as an artifact built by another CI
+/// job or downloaded by `cargo xtask test-artifact`, else the local build.
+fn fixture_path() -> String {
+    match std::env::var("U128_FIXTURE_SO") {
+        Ok(path) => path.strip_suffix(".so").unwrap_or(&path).to_string(),
+        Err(_) => PROGRAM_PATH.to_string(),
+    }
+}
+
+/// Where mollusk finds the program built from `package` (without the `.so`),
+/// for the standalone programs in `programs/`.
+fn program_path(package: &str) -> String {
+    format!(
+        "target/bpfel-unknown-none/release/lib{}",
+        package.replace('-', "_")
+    )
+}
+
+/// Instruction data for `op`: the header carrying `param`, followed by
+/// `operands`.
+fn op_data(op: Op, param: u64, operands: &[u128]) -> Vec<u8> {
+    let mut data = vec![0; OPERANDS_OFFSET];
+    data[0] = op as u8;
+    data[PARAM_OFFSET..OPERANDS_OFFSET].copy_from_slice(&param.to_le_bytes());
+    data.extend(operands.iter().flat_map(|x| x.to_le_bytes()));
+    data
+}
+
+fn param_instruction(op: Op, param: u64, operands: &[u128]) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID.into(),
+        accounts: vec![],
+        data: op_data(op, param, operands),
+    }
+}
+
+fn op_instruction(op: Op, operands: &[u128]) -> Instruction {
+    param_instruction(op, 0, operands)
+}
+
+/// What the program should return for a two-operand `op`, computed on the host.
+fn host_reference(op: Op, a: u128, b: u128) -> u64 {
+    ops::high_word(ops::binary(op, a, b).expect("not a two-operand op"))
+}
+
+/// Run `op`, returning the failure unless it succeeds with `expected` as its
+/// return data.
+fn check_op(
+    mollusk: &Mollusk,
+    op: Op,
+    param: u64,
+    operands: &[u128],
+    expected: u64,
+) -> Option<Failure> {
+    let run = run(mollusk, &param_instruction(op, param, operands), &[]);
+    if run.result.is_ok() && run.return_value == Some(expected) {
+        return None;
+    }
+    Some(Failure {
+        op,
+        param,
+        operands: operands.to_vec(),
+        expected,
+        actual: format!("{:?} returning {:x?}", run.result, run.return_value),
+    })
+}
+
+/// Run `op` and assert it succeeds with `expected` as its return data.
+fn assert_op(mollusk: &Mollusk, op: Op, operands: &[u128], expected: u64) {
+    assert_op_param(mollusk, op, 0, operands, expected);
+}
+
+/// Like `assert_op`, with `param` in the header.
+fn assert_op_param(mollusk: &Mollusk, op: Op, param: u64, operands: &[u128], expected: u64) {
+    assert_instruction(
+        mollusk,
+        &param_instruction(op, param, operands),
+        &[],
+        expected,
+    );
+}
+
+/// Run `instruction` against `accounts` and assert it succeeds with
+/// `expected` as its return data.
+fn assert_instruction(
+    mollusk: &Mollusk,
+    instruction: &Instruction,
+    accounts: &[(Pubkey, Account)],
+    expected: u64,
+) {
+    mollusk.process_and_validate_instruction(
+        instruction,
+        accounts,
+        &[
+            Check::success(),
+            Check::return_data(&expected.to_le_bytes()),
+        ],
+    );
+}
+
+/// Run `instruction` with a zeroed `out_len`-byte `OUT_KEY` account as its
+/// only account, and assert it succeeds with `expected_return` as its return
+/// data and `expected_account` as that account's data.
+fn assert_op_with_output(
+    mollusk: &Mollusk,
+    instruction: Instruction,
+    out_len: usize,
+    expected_return: u64,
+    expected_account: &[u8],
+) {
+    assert_op_with_account(
+        mollusk,
+        instruction,
+        vec![0; out_len],
+        expected_return,
+        expected_account,
+    );
+}
+
+/// Like `assert_op_with_output`, with the `OUT_KEY` account starting out as
+/// `initial`.
+fn assert_op_with_account(
+    mollusk: &Mollusk,
+    mut instruction: Instruction,
+    initial: Vec<u8>,
+    expected_return: u64,
+    expected_account: &[u8],
+) {
+    instruction.accounts = vec![AccountMeta::new(OUT_KEY.into(), false)];
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &[data_account(OUT_KEY, initial)],
+        &[
+            Check::success(),
+            Check::return_data(&expected_return.to_le_bytes()),
+            strict_account_check(OUT_KEY, expected_account),
+        ],
+    );
+}
+
+/// `a * b` as 256 little-endian bits, by 32-bit schoolbook multiplication:
+/// a different decomposition from the program's, so the two can't share a
+/// carry bug.
+fn wide_product_bytes(a: u128, b: u128) -> Vec<u8> {
+    let limbs = |x: u128| -> Vec<u64> { (0..4).map(|i| (x >> (32 * i)) as u32 as u64).collect() };
+    let (a, b) = (limbs(a), limbs(b));
+    let mut out = [0u64; 8];
+    for i in 0..4 {
+        let mut carry = 0;
+        for j in 0..4 {
+            let t = out[i + j] + a[i] * b[j] + carry;
+            out[i + j] = t & 0xffff_ffff;
+            carry = t >> 32;
+        }
+        out[i + 4] = carry;
+    }
+    out.iter()
+        .flat_map(|&limb| (limb as u32).to_le_bytes())
+        .collect()
+}
+
+/// A program-owned account holding `data`.
+fn data_account(key: [u8; 32], data: Vec<u8>) -> (Pubkey, Account) {
+    let mut account = Account::new(ACCOUNT_LAMPORTS, 0, &PROGRAM_OWNER);
+    account.data = data;
+    (key.into(), account)
+}
+
+/// Check that the `data_account` at `key` ends the run holding `data`, with
+/// its lamports, owner, size and executable flag as the account was set up,
+/// so a program that writes its result but also touches the account's
+/// metadata fails.
+fn strict_account_check(key: [u8; 32], data: &[u8]) -> Check {
+    Check::account(&key.into())
+        .data(data)
+        .lamports(ACCOUNT_LAMPORTS)
+        .owner(&PROGRAM_OWNER)
+        .executable(false)
+        .space(data.len())
+        .build()
+}
+
+fn u128_bytes(values: &[u128]) -> Vec<u8> {
+    values.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+/// Run `op` and assert it fails with `error`.
+fn assert_op_err(mollusk: &Mollusk, op: Op, operands: &[u128], error: Error) {
+    mollusk.process_and_validate_instruction(
+        &op_instruction(op, operands),
+        &[],
+        &[Check::instruction_err(InstructionError::Custom(
+            error as u32,
+        ))],
+    );
+}
+
+#[test]
+pub fn test() {
+    let mollusk = Mollusk::new(
+        &PROGRAM_ID.into(), //
+        &fixture_path(),
+    );
+    let input_data: [i128; 2] = [10, 20];
+    let instruction = op_instruction(Op::Mul, &input_data.map(|x| x as u128));
+    mollusk.process_and_validate_instruction(&instruction, &[], &[Check::success()]);
+}
+
+/// Ops whose cost `compute_units_per_op` reports, all run on two operands.
+const CU_REPORT_OPS: &[Op] = &[
+    Op::Mul,
+    Op::And,
+    Op::Or,
+    Op::Xor,
+    Op::Not,
+    Op::SaturatingMul,
+    Op::SaturatingAdd,
+    Op::MulSchoolbook,
+    Op::MixedSignMul,
+    Op::Div,
+    Op::BitLength,
+    Op::MulPopcount,
+    Op::MulCrc,
+    Op::MulLaws,
+];
+/// `param` for the `CU_REPORT_OPS` that loop: enough iterations for the
+/// loop body to dominate, well inside the compute budget.
+const CU_REPORT_ITERATIONS: u64 = 100;
+
+/// Compute units each op in `CU_REPORT_OPS` takes over the same seeded
+/// operands, one `compute units: <op> <total>` line per op. Results aren't
+/// checked here; the other tests cover that.
+#[test]
+fn compute_units_per_op() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    for &op in CU_REPORT_OPS {
+        println!(
+            "{CU_REPORT_PREFIX} {op:?} {}",
+            report_compute_units(&mollusk, op)
+        );
+    }
+}
+
+/// Total compute units of `op` over `DEFAULT_VECTORS` seeded operand pairs.
+fn report_compute_units(mollusk: &Mollusk, op: Op) -> u64 {
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    let mut total = 0;
+    for _ in 0..DEFAULT_VECTORS {
+        let operands = [rng.next_u128(), rng.next_u128() | 1];
+        let instruction = param_instruction(op, CU_REPORT_ITERATIONS, &operands);
+        total += run(mollusk, &instruction, &[])
+            .expect_success()
+            .compute_units;
+    }
+    total
+}
+
+/// Checked-in compute units of each op in `CU_REPORT_OPS`, as measured by
+/// `report_compute_units`: one `Name=units` line per op. Blank lines and
+/// lines starting with `#` are ignored.
+const CU_BASELINE_FILE: &str = "cu-baseline.txt";
+/// First line of `CU_BASELINE_FILE`, naming what writes it.
+const CU_BASELINE_HEADER: &str =
+    "# Written by `UPDATE_CU_BASELINE=1 cargo test compute_units_match_baseline`.\n";
+/// How far past its baseline an op's cost may go, in percent, before
+/// `compute_units_match_baseline` fails. The same default as `cu-compare`.
+const CU_BASELINE_TOLERANCE_PERCENT: u64 = 5;
+
+/// Parse `CU_BASELINE_FILE`, panicking on the first malformed line.
+fn read_cu_baseline(contents: &str) -> Vec<(String, u64)> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(index, line)| {
+            let (name, units) = line
+                .split_once('=')
+                .and_then(|(name, units)| Some((name.trim(), units.trim().parse().ok()?)))
+                .unwrap_or_else(|| {
+                    panic!(
+                        "{CU_BASELINE_FILE}:{}: expected `Name=units`, got `{line}`",
+                        index + 1
+                    )
+                });
+            (name.to_string(), units)
+        })
+        .collect()
+}
+
+fn write_cu_baseline(measured: &[(String, u64)]) {
+    let mut contents = String::from(CU_BASELINE_HEADER);
+    for (name, units) in measured {
+        contents.push_str(&format!("{name}={units}\n"));
+    }
+    std::fs::write(CU_BASELINE_FILE, contents).unwrap();
+}
+
+/// Fail if any op in `CU_REPORT_OPS` is missing from `CU_BASELINE_FILE` or
+/// costs more than `CU_BASELINE_TOLERANCE_PERCENT` over its entry there.
+/// Until the file has any entries there is nothing to compare against, and
+/// the test only prints the measured costs.
+/// After an intentional change, rerun with `UPDATE_CU_BASELINE=1` to rewrite
+/// the file and commit the new numbers.
+#[test]
+fn compute_units_match_baseline() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let measured: Vec<(String, u64)> = CU_REPORT_OPS
+        .iter()
+        .map(|&op| (format!("{op:?}"), report_compute_units(&mollusk, op)))
+        .collect();
+    if env_or("UPDATE_CU_BASELINE", 0) == 1 {
+        write_cu_baseline(&measured);
+        println!("{CU_BASELINE_FILE} updated");
+        return;
+    }
+
+    let contents = std::fs::read_to_string(CU_BASELINE_FILE).unwrap_or_default();
+    let baseline = read_cu_baseline(&contents);
+    if baseline.is_empty() {
+        for (name, units) in &measured {
+            println!("{name}: {units} compute units");
+        }
+        println!(
+            "{CU_BASELINE_FILE} has no entries yet; rerun with UPDATE_CU_BASELINE=1 to fill it in"
+        );
+        return;
+    }
+    let mut failures = Vec::new();
+    for (name, units) in &measured {
+        let Some(&(_, expected)) = baseline.iter().find(|(entry, _)| entry == name) else {
+            failures.push(format!(
+                "{name}: {units} compute units, not in {CU_BASELINE_FILE}"
+            ));
+            continue;
+        };
+        let change = (*units as f64 - expected as f64) * 100.0 / expected.max(1) as f64;
+        let line = format!("{name}: baseline {expected}, now {units} ({change:+.1}%)");
+        if *units > expected + expected * CU_BASELINE_TOLERANCE_PERCENT / 100 {
+            failures.push(line);
+        } else {
+            println!("{line}");
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "compute units missing from {CU_BASELINE_FILE} or more than \
+         {CU_BASELINE_TOLERANCE_PERCENT}% over it:\n{}\n\
+         if intended, rerun with UPDATE_CU_BASELINE=1 and commit the result",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn standalone_mul_program_matches_host() {
+    let mollusk = Mollusk::new(&MUL_PROGRAM_ID.into(), &program_path("u128-mul"));
+    let instruction = |data: Vec<u8>| Instruction {
+        program_id: MUL_PROGRAM_ID.into(),
+        accounts: vec![],
+        data,
+    };
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b) = (rng.next_u128(), rng.next_u128());
+        let expected = ops::high_word(a.wrapping_mul(b));
+        assert_instruction(&mollusk, &instruction(u128_bytes(&[a, b])), &[], expected);
+    }
+    mollusk.process_and_validate_instruction(
+        &instruction(vec![0; 31]),
+        &[],
+        &[Check::instruction_err(InstructionError::Custom(1))],
+    );
+}
+
+/// Uninitialized registers or stack slots leaking into a 128-bit result
+/// would show up as runs of the same instruction disagreeing.
+#[test]
+fn repeated_runs_are_identical() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let (a, b) = (
+        0x0123_4567_89ab_cdef_fedc_ba98_7654_3210,
+        0x9e37_79b9_7f4a_7c15_f39c_c060_5ced_c835,
+    );
+    let output = |op: Op| {
+        let mut instruction = op_instruction(op, &[a, b]);
+        instruction.accounts = vec![AccountMeta::new(OUT_KEY.into(), false)];
+        instruction
+    };
+    let cases = [
+        (param_instruction(Op::Mul, 100, &[a, b]), vec![]),
+        (op_instruction(Op::MulDivIdentity, &[a, b]), vec![]),
+        (
+            output(Op::WideMul),
+            vec![data_account(OUT_KEY, vec![0; 32])],
+        ),
+        (output(Op::MulDiv), vec![data_account(OUT_KEY, vec![0; 32])]),
+    ];
+    for (instruction, accounts) in &cases {
+        let first = run(&mollusk, instruction, accounts).expect_success();
+        for attempt in 1..REPEATED_RUNS {
+            let again = run(&mollusk, instruction, accounts);
+            let op = instruction.data[0];
+            assert_eq!(again.result, first.result, "op {op}, run {attempt}");
+            assert_eq!(
+                again.return_value, first.return_value,
+                "op {op}, run {attempt}"
+            );
+            assert_eq!(
+                again.output_account, first.output_account,
+                "op {op}, run {attempt}"
+            );
+            assert_eq!(
+                again.compute_units, first.compute_units,
+                "op {op}, run {attempt}"
+            );
+        }
+    }
+}
+
+#[test]
+fn compute_budget_exhaustion_fails_cleanly() {
+    let mut mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let (a, b, iterations) = (3, 5, 1000);
+    let instruction = param_instruction(Op::Mul, iterations, &[a, b]);
+    let expected = ops::high_word(ops::mul_loop(a, b, iterations));
+
+    mollusk.compute_budget.compute_unit_limit = MAX_COMPUTE_UNITS;
+    let needed = run(&mollusk, &instruction, &[])
+        .expect_success()
+        .compute_units;
+
+    // Exactly enough budget still completes with the right result...
+    mollusk.compute_budget.compute_unit_limit = needed;
+    assert_instruction(&mollusk, &instruction, &[], expected);
+
+    // ...and one unit less aborts rather than returning a partial product.
+    mollusk.compute_budget.compute_unit_limit = needed - 1;
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &[],
+        &[Check::instruction_err(
+            InstructionError::ComputationalBudgetExceeded,
+        )],
+    );
+}
+
+#[test]
+fn independent_multiplies_per_compute_budget() {
+    let mut mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    mollusk.compute_budget.compute_unit_limit = MAX_COMPUTE_UNITS;
+    let (a, b) = (
+        0x0123_4567_89ab_cdef_fedc_ba98_7654_3210,
+        0x9e37_79b9_7f4a_7c15_f39c_c060_5ced_c834,
+    );
+    let run_count = |count: u64| {
+        let mut instruction = param_instruction(Op::MulCount, count, &[a, b]);
+        instruction.accounts = vec![AccountMeta::new(OUT_KEY.into(), false)];
+        run(
+            &mollusk,
+            &instruction,
+            &[data_account(OUT_KEY, vec![0; 32])],
+        )
+    };
+    let assert_completes = |count: u64| {
+        let run = run_count(count).expect_success();
+        assert_eq!(run.return_value, Some(count));
+        assert_eq!(
+            run.output_u128(0) as u64,
+            count,
+            "counter after {count} multiplies"
+        );
+        let expected = ops::mul_count(a, b, count, |_| Ok::<_, ()>(())).unwrap();
+        assert_eq!(run.output_u128(1), expected, "{count} multiplies");
+    };
+    for count in [0, 1, 2, 100] {
+        assert_completes(count);
+    }
+
+    // Double the count until the budget runs out, then bisect for the
+    // largest count that still fits.
+    let mut fits = 1;
+    while run_count(fits * 2).result.is_ok() {
+        fits *= 2;
+    }
+    let mut exceeds = fits * 2;
+    while exceeds - fits > 1 {
+        let mid = fits + (exceeds - fits) / 2;
+        if run_count(mid).result.is_ok() {
+            fits = mid;
+        } else {
+            exceeds = mid;
+        }
+    }
+    println!("{fits} independent multiplies fit in {MAX_COMPUTE_UNITS} compute units");
+    assert_completes(fits);
+    assert_eq!(
+        run_count(exceeds).result,
+        ProgramResult::Failure(InstructionError::ComputationalBudgetExceeded),
+        "{exceeds} multiplies"
+    );
+
+    // An account without room for the products is rejected before any work.
+    let mut instruction = param_instruction(Op::MulCount, 1, &[a, b]);
+    instruction.accounts = vec![AccountMeta::new(OUT_KEY.into(), false)];
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &[data_account(OUT_KEY, vec![0; 24])],
+        &[Check::instruction_err(InstructionError::Custom(
+            Error::InsufficientInput as u32,
+        ))],
+    );
+}
+
+#[test]
+fn multiply_struct_fields_in_place() {
+    // The `MulRecord` layout, spelled out: `a`, `b`, `flag`, padding, then
+    // `product` at 48, its 16-byte alignment intact.
+    assert_eq!(core::mem::offset_of!(ops::MulRecord, product), 48);
+    assert_eq!(core::mem::size_of::<ops::MulRecord>(), 64);
+    let record = |a: u128, b: u128, flag: u64, product: u128| {
+        let mut data = Vec::with_capacity(64);
+        data.extend_from_slice(&a.to_le_bytes());
+        data.extend_from_slice(&b.to_le_bytes());
+        data.extend_from_slice(&flag.to_le_bytes());
+        data.extend_from_slice(&[0; 8]);
+        data.extend_from_slice(&product.to_le_bytes());
+        data
+    };
+
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let assert_record = |a: u128, b: u128, flag: u64, expected: u128| {
+        assert_op_with_account(
+            &mollusk,
+            op_instruction(Op::MulStruct, &[]),
+            record(a, b, flag, 0x5a5a),
+            ops::high_word(expected),
+            &record(a, b, flag, expected),
+        );
+    };
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b) = (rng.next_u128(), rng.next_u128());
+        assert_record(a, b, 0, a.wrapping_mul(b));
+        assert_record(a, b, 1, a.saturating_mul(b));
+    }
+    assert_record(u128::MAX, 2, 0, u128::MAX - 1);
+    assert_record(u128::MAX, 2, u64::MAX, u128::MAX);
+
+    // A record cut short is rejected rather than read past.
+    let mut instruction = op_instruction(Op::MulStruct, &[]);
+    instruction.accounts = vec![AccountMeta::new(OUT_KEY.into(), false)];
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &[data_account(OUT_KEY, vec![0; 48])],
+        &[Check::instruction_err(InstructionError::Custom(
+            Error::InsufficientInput as u32,
+        ))],
+    );
+}
+
+#[test]
+fn seeded_operands_match_host() {
+    let seed = env_or("U128_SEED", DEFAULT_SEED);
+    let count = env_or("U128_VECTORS", DEFAULT_VECTORS);
+    println!("operand seed: {seed} (replay with U128_SEED={seed})");
+
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    check_seeded_vectors(&mollusk, "seeded vectors", seed, count, host_reference).finish();
+}
+
+/// Check every `Op::BINARY` op on `count` operand pairs drawn from `seed`
+/// against `reference`.
+fn check_seeded_vectors(
+    mollusk: &Mollusk,
+    label: &'static str,
+    seed: u64,
+    count: usize,
+    reference: fn(Op, u128, u128) -> u64,
+) -> Progress {
+    let mut rng = SplitMix64(seed);
+    let mut progress = Progress::new(label, count * Op::BINARY.len());
+    for _ in 0..count {
+        let (a, b) = (rng.next_u128(), rng.next_u128());
+        for &op in Op::BINARY {
+            progress.record(check_op(mollusk, op, 0, &[a, b], reference(op, a, b)));
+        }
+    }
+    progress
+}
+
+/// `host_reference` off by one, standing in for a miscompiled program.
+fn faulty_reference(op: Op, a: u128, b: u128) -> u64 {
+    host_reference(op, a, b).wrapping_add(1)
+}
+
+/// If the harness compared the wrong values or skipped the comparison,
+/// every other test here would pass regardless of what the program returned.
+/// A reference that is wrong on every vector must fail on every vector.
+#[test]
+fn wrong_reference_is_detected() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let progress = check_seeded_vectors(
+        &mollusk,
+        "faulty reference",
+        DEFAULT_SEED,
+        DEFAULT_VECTORS,
+        faulty_reference,
+    );
+    assert_eq!(
+        progress.failures.len(),
+        progress.total,
+        "a wrong reference passed some vectors"
+    );
+
+    let (a, b) = (u128::MAX, u128::MAX);
+    let asserted = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        assert_op(&mollusk, Op::Mul, &[a, b], faulty_reference(Op::Mul, a, b));
+    }));
+    assert!(asserted.is_err(), "assert_op accepted a wrong return value");
+}
+
+#[test]
+fn program_id_selects_op() {
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    let (a, b) = (rng.next_u128(), rng.next_u128());
+    let run_at = |last_byte: u8| {
+        let mut program_id = PROGRAM_ID;
+        program_id[31] = last_byte;
+        let mollusk = Mollusk::new(&program_id.into(), &fixture_path());
+        let mut data = op_data(Op::Mul, 0, &[a, b]);
+        data[0] = PROGRAM_ID_OPCODE;
+        let instruction = Instruction {
+            program_id: program_id.into(),
+            accounts: vec![],
+            data,
+        };
+        run(&mollusk, &instruction, &[])
+    };
+    for &op in Op::BINARY {
+        let run = run_at(op as u8).expect_success();
+        assert_eq!(
+            run.return_value,
+            Some(host_reference(op, a, b)),
+            "program ID ending in {op:?}"
+        );
+    }
+    assert_eq!(
+        run_at(PROGRAM_ID_OPCODE).result,
+        ProgramResult::Failure(InstructionError::Custom(Error::UnknownOp as u32))
+    );
+}
+
+/// Operand pairs with both 64-bit halves of each operand set, so no multiply
+/// of them can be narrowed to 64 bits and every one goes through the
+/// `__multi3` libcall. `cargo xtask libcall-check` runs these against a build
+/// it has checked links that libcall.
+fn libcall_operands() -> Vec<(u128, u128)> {
+    let halves_set = |value: u128| value | 1 | 1 << 64;
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    let mut operands = vec![(u128::MAX, u128::MAX), (1 << 64 | 1, 1 << 64 | 1)];
+    for _ in 0..DEFAULT_VECTORS {
+        operands.push((halves_set(rng.next_u128()), halves_set(rng.next_u128())));
+    }
+    operands
+}
+
+#[test]
+fn libcall_operands_match_host() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    for (a, b) in libcall_operands() {
+        assert_op_param(
+            &mollusk,
+            Op::Mul,
+            1,
+            &[a, b],
+            ops::high_word(a.wrapping_mul(b)),
+        );
+    }
+}
+
+#[test]
+fn exhaustive_small_products() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let total = (EXHAUSTIVE_RANGE * EXHAUSTIVE_RANGE) as usize;
+    let mut progress = Progress::new("small products", total);
+    for x in 0..EXHAUSTIVE_RANGE {
+        for y in 0..EXHAUSTIVE_RANGE {
+            // Only the high word is returned, so lift `x` into it; a single
+            // iteration then returns exactly `x * y`.
+            progress.record(check_op(
+                &mollusk,
+                Op::Mul,
+                1,
+                &[x << 64, y],
+                (x * y) as u64,
+            ));
+        }
+    }
+    progress.finish();
+}
+
+#[test]
+fn schoolbook_multiply_matches_native() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    let mut units = [0; 2];
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b) = (rng.next_u128(), rng.next_u128());
+        let expected = ops::high_word(ops::mul_loop(a, b, 10));
+        for (op, units) in [Op::Mul, Op::MulSchoolbook].into_iter().zip(&mut units) {
+            let run = run(&mollusk, &param_instruction(op, 10, &[a, b]), &[]).expect_success();
+            assert_eq!(run.return_value, Some(expected), "{op:?} {a:#x} {b:#x}");
+            *units += run.compute_units;
+        }
+    }
+    println!(
+        "compute units over {DEFAULT_VECTORS} runs: native {}, schoolbook {}",
+        units[0], units[1]
+    );
+}
+
+#[test]
+fn interleaved_signed_and_unsigned_multiplies() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        // Negative as `i128`, so the chains diverge within a few iterations.
+        let (a, b) = (rng.next_u128() | 1 << 127, rng.next_u128() | 1 << 127 | 1);
+        for iterations in [1, 2, 50] {
+            let (signed, unsigned) = ops::mixed_sign_mul(a, b, iterations);
+            let expected = ops::mixed_sign_word(signed, unsigned);
+            assert_op_param(&mollusk, Op::MixedSignMul, iterations, &[a, b], expected);
+        }
+    }
+    // -1 * 2 = -2: halved arithmetically it stays negative, logically it
+    // loses the sign bit.
+    let (signed, unsigned) = ops::mixed_sign_mul(u128::MAX, 2, 1);
+    assert_eq!((signed, unsigned), (-1, u128::MAX >> 1));
+    assert_op_param(
+        &mollusk,
+        Op::MixedSignMul,
+        1,
+        &[u128::MAX, 2],
+        ops::mixed_sign_word(-1, u128::MAX >> 1),
+    );
+}
+
+#[test]
+fn bitwise_ops_process_both_words() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let low = 0x0123_4567_89ab_cdef_u128;
+    let high_only = [(0xf0f0_u128 << 64) | low, (0x0ff0_u128 << 64) | low];
+    let low_only = [(0xabcd_u128 << 64) | 0xf0f0, (0xabcd_u128 << 64) | 0x0ff0];
+
+    for [a, b] in [high_only, low_only] {
+        for op in [Op::And, Op::Or, Op::Xor, Op::Not] {
+            assert_op(&mollusk, op, &[a, b], host_reference(op, a, b));
+        }
+    }
+    // Operands equal in the high word must not leak low-word bits upward.
+    assert_op(&mollusk, Op::Xor, &low_only, 0);
+    assert_op(&mollusk, Op::Xor, &high_only, 0xf0f0 ^ 0x0ff0);
+    assert_op(&mollusk, Op::Not, &low_only, !0xabcd);
+}
+
+#[test]
+fn second_operand_read_from_its_own_slot() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    // Complementary bit patterns: any read of `a` in place of `b` (or the
+    // other way round) shows up as 0 from Xor and as `a` or `b` from And.
+    let a = 0xaaaa_aaaa_aaaa_aaaa_5555_5555_5555_5555_u128;
+    let b = !a;
+    assert_op(&mollusk, Op::Xor, &[a, b], u64::MAX);
+    assert_op(&mollusk, Op::And, &[a, b], 0);
+    assert_op(&mollusk, Op::Or, &[a, b], u64::MAX);
+
+    // Division isn't symmetric: swapped operands give 0, aliased ones 1.
+    assert_op(&mollusk, Op::Div, &[100 << 64, 2], 50);
+    assert_op(&mollusk, Op::Div, &[2, 100 << 64], 0);
+
+    // A third operand past the second must not disturb it.
+    assert_op(&mollusk, Op::Div, &[100 << 64, 4, 7], 25);
+}
+
+#[test]
+fn saturating_ops_clamp_on_overflow() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let max = u64::MAX;
+    assert_op(&mollusk, Op::SaturatingMul, &[u128::MAX, 2], max);
+    assert_op(&mollusk, Op::SaturatingMul, &[1 << 64, 1 << 64], max);
+    assert_op(&mollusk, Op::SaturatingMul, &[1 << 63, 1 << 64], 1 << 63);
+    assert_op(&mollusk, Op::SaturatingAdd, &[u128::MAX, 1], max);
+    assert_op(&mollusk, Op::SaturatingAdd, &[1 << 127, 1 << 127], max);
+    assert_op(&mollusk, Op::SaturatingAdd, &[u128::MAX - 1, 1], max);
+    assert_op(&mollusk, Op::SaturatingAdd, &[u64::MAX as u128, 1], 1);
+
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        // Half-width operands never saturate the product; full-width ones
+        // almost always do.
+        let (a, b) = (rng.next_u128(), rng.next_u128());
+        for [a, b] in [[a >> 64, b >> 64], [a, b]] {
+            for op in [Op::SaturatingMul, Op::SaturatingAdd] {
+                assert_op(&mollusk, op, &[a, b], host_reference(op, a, b));
+            }
+        }
+    }
+}
+
+#[test]
+fn average_without_overflow() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    // The 129-bit sum, carry and all, shifted down by one.
+    let assert_average = |a: u128, b: u128| {
+        let (sum, carry) = a.overflowing_add(b);
+        let average = (carry as u128) << 127 | sum >> 1;
+        assert_op(&mollusk, Op::Average, &[a, b], ops::high_word(average));
+    };
+    // `a + b` overflows for all of these; the average never does.
+    assert_average(u128::MAX, u128::MAX);
+    assert_average(u128::MAX, u128::MAX - 1);
+    assert_average(u128::MAX, 1);
+    assert_average(1 << 127, 1 << 127);
+    // The carry between words, and rounding down an odd sum.
+    assert_average(u64::MAX as u128, 1);
+    assert_average(u64::MAX as u128, u64::MAX as u128 + 2);
+    assert_average(0, 1);
+    assert_average(0, u128::MAX);
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b) = (rng.next_u128(), rng.next_u128());
+        assert_average(a, b);
+        assert_average(a | 1 << 127, b | 1 << 127);
+    }
+}
+
+#[test]
+fn dot_product_of_operand_pairs() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    // (2^64 * 2^63) * 2 = 2^128 wraps to zero, leaving only 3 * 2^64.
+    let operands = [1 << 64, 1 << 63, 1 << 64, 1 << 63, 3, 1 << 64];
+    assert_op_param(&mollusk, Op::DotProduct, 3, &operands, 3);
+    // Only the first `param` pairs count.
+    assert_op_param(&mollusk, Op::DotProduct, 1, &operands, 1 << 63);
+    assert_op_param(&mollusk, Op::DotProduct, 0, &operands, 0);
+
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    let values: Vec<u128> = (0..64).map(|_| rng.next_u128()).collect();
+    let pairs = values.chunks(2).map(|pair| (pair[0], pair[1]));
+    let expected = ops::high_word(ops::dot_product(pairs));
+    assert_op_param(&mollusk, Op::DotProduct, 32, &values, expected);
+
+    mollusk.process_and_validate_instruction(
+        &param_instruction(Op::DotProduct, 33, &values),
+        &[],
+        &[Check::instruction_err(InstructionError::Custom(
+            Error::InsufficientInput as u32,
+        ))],
+    );
+}
+
+#[test]
+fn dot_product_into_256_bits() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    // The exact sum, added up a `u64` limb at a time so the 128-bit carry is
+    // just another limb boundary.
+    let host_sum = |values: &[u128]| {
+        let mut sum = [0u64; 4];
+        for pair in values.chunks(2) {
+            let mut carry = 0u128;
+            for (limb, product_limb) in sum
+                .iter_mut()
+                .zip(ops::widening_mul_limbs(pair[0], pair[1]))
+            {
+                let total = *limb as u128 + product_limb as u128 + carry;
+                *limb = total as u64;
+                carry = total >> 64;
+            }
+        }
+        let half = |low: u64, high: u64| (high as u128) << 64 | low as u128;
+        (half(sum[0], sum[1]), half(sum[2], sum[3]))
+    };
+    let assert_dot = |values: &[u128]| {
+        let (low, high) = host_sum(values);
+        let instruction = param_instruction(Op::DotProductWide, values.len() as u64 / 2, values);
+        assert_op_with_output(
+            &mollusk,
+            instruction,
+            32,
+            ops::high_word(high),
+            &u128_bytes(&[low, high]),
+        );
+    };
+    // Low halves of `u128::MAX` that carry into the high half on every add,
+    // and products large enough that the 256-bit sum wraps.
+    assert_dot(&[u128::MAX, 1, u128::MAX, 1, u128::MAX, 1]);
+    assert_dot(&[
+        u128::MAX,
+        u128::MAX,
+        u128::MAX,
+        u128::MAX,
+        u128::MAX,
+        u128::MAX,
+    ]);
+    assert_dot(&[1 << 64, 1 << 64, 1 << 127, 2]);
+    assert_dot(&[]);
+
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    let values: Vec<u128> = (0..64).map(|_| rng.next_u128()).collect();
+    assert_dot(&values);
+    for pairs in 1..=8 {
+        assert_dot(&values[..pairs * 2]);
+    }
+}
+
+#[test]
+fn mixed_width_elements_summed_into_256_bits() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let sum_instruction = |width: u8, count: u64, elements: &[u8]| {
+        let mut data = op_data(Op::WideSum, count, &[]);
+        data[WIDTH_OFFSET] = width;
+        data.extend(elements);
+        Instruction {
+            program_id: PROGRAM_ID.into(),
+            accounts: vec![AccountMeta::new(OUT_KEY.into(), false)],
+            data,
+        }
+    };
+    // Reference sum in 32-bit limbs, independent of `ops::add_wide`.
+    let host_sum = |elements: &[u8], width: usize| {
+        let mut limbs = [0u64; 8];
+        for element in elements.chunks(width) {
+            let mut carry = 0;
+            for (i, limb) in limbs.iter_mut().enumerate() {
+                let piece = element.get(i * 4..i * 4 + 4).map_or(0, |bytes| {
+                    u32::from_le_bytes(bytes.try_into().unwrap()) as u64
+                });
+                let total = *limb + piece + carry;
+                *limb = total & 0xffff_ffff;
+                carry = total >> 32;
+            }
+        }
+        limbs
+            .iter()
+            .flat_map(|&limb| (limb as u32).to_le_bytes())
+            .collect::<Vec<u8>>()
+    };
+
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for width in [8, 16, 32] {
+        for count in [0, 1, 7, 64] {
+            // All-ones elements make every addition carry.
+            for ones in [false, true] {
+                let elements: Vec<u8> = (0..count * width / 16 + 1)
+                    .flat_map(|_| if ones { u128::MAX } else { rng.next_u128() }.to_le_bytes())
+                    .take(count * width)
+                    .collect();
+                let expected = host_sum(&elements, width);
+                let high = u64::from_le_bytes(expected[16..24].try_into().unwrap());
+                mollusk.process_and_validate_instruction(
+                    &sum_instruction(width as u8, count as u64, &elements),
+                    &[data_account(OUT_KEY, vec![0; 32])],
+                    &[
+                        Check::success(),
+                        Check::return_data(&high.to_le_bytes()),
+                        strict_account_check(OUT_KEY, &expected),
+                    ],
+                );
+            }
+        }
+    }
+
+    let err = |error: Error| {
+        [Check::instruction_err(InstructionError::Custom(
+            error as u32,
+        ))]
+    };
+    let accounts = [data_account(OUT_KEY, vec![0; 32])];
+    mollusk.process_and_validate_instruction(
+        &sum_instruction(12, 1, &[0; 12]),
+        &accounts,
+        &err(Error::UnknownOp),
+    );
+    mollusk.process_and_validate_instruction(
+        &sum_instruction(32, 2, &[0; 63]),
+        &accounts,
+        &err(Error::InsufficientInput),
+    );
+}
+
+/// Instruction data for `HexMul` with `text` after the header.
+fn hex_instruction(text: &str) -> Instruction {
+    let mut instruction = op_instruction(Op::HexMul, &[]);
+    instruction.data.extend(text.as_bytes());
+    instruction
+}
+
+#[test]
+fn unaligned_operands_read_correctly() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let instruction = |operands: &[u128]| {
+        let mut data = op_data(Op::MulUnaligned, 0, &[]);
+        data.resize(UNALIGNED_OPERANDS_OFFSET, 0);
+        data.extend(u128_bytes(operands));
+        Instruction {
+            program_id: PROGRAM_ID.into(),
+            accounts: vec![],
+            data,
+        }
+    };
+    // The instruction data starts 8-byte aligned in the input, so these
+    // loads are odd-aligned. They must still see every byte of the operand,
+    // in order.
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b) = (rng.next_u128(), rng.next_u128());
+        let expected = ops::high_word(a.wrapping_mul(b));
+        assert_instruction(&mollusk, &instruction(&[a, b]), &[], expected);
+    }
+    assert_instruction(&mollusk, &instruction(&[1 << 64, u128::MAX]), &[], u64::MAX);
+
+    // A second operand short by a byte is an error, not a read past the end.
+    let mut truncated = instruction(&[3, 5]);
+    truncated.data.pop();
+    mollusk.process_and_validate_instruction(
+        &truncated,
+        &[],
+        &[Check::instruction_err(InstructionError::Custom(
+            Error::InsufficientInput as u32,
+        ))],
+    );
+}
+
+#[test]
+fn hex_operands_parsed_in_program() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let hex = |x: u128| format!("{x:032x}");
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b) = (rng.next_u128(), rng.next_u128());
+        let expected = ops::high_word(a.wrapping_mul(b));
+        let text = hex(a) + &hex(b);
+        assert_instruction(&mollusk, &hex_instruction(&text), &[], expected);
+        assert_instruction(
+            &mollusk,
+            &hex_instruction(&text.to_uppercase()),
+            &[],
+            expected,
+        );
+    }
+    let text = "0000000000000001000000000000000000000000000000000000000000000003";
+    assert_instruction(&mollusk, &hex_instruction(text), &[], 3);
+
+    let invalid = InstructionError::Custom(Error::InvalidHex as u32);
+    let short = InstructionError::Custom(Error::InsufficientInput as u32);
+    let bad_text = hex(1).replace('1', "g") + &hex(3);
+    for (text, error) in [(bad_text, invalid), (hex(1) + &hex(3)[1..], short)] {
+        mollusk.process_and_validate_instruction(
+            &hex_instruction(&text),
+            &[],
+            &[Check::instruction_err(error)],
+        );
+    }
+}
+
+#[test]
+fn carry_count_of_chained_adds() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    // The first add of MAX into zero can't carry; every later one does.
+    assert_op(&mollusk, Op::CarryCount, &[u128::MAX; 8], 7);
+    assert_op(&mollusk, Op::CarryCount, &[u128::MAX, 1], 1);
+    assert_op(&mollusk, Op::CarryCount, &[1 << 127, 1 << 126, 1 << 126], 1);
+    assert_op(&mollusk, Op::CarryCount, &[], 0);
+
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    let values: Vec<u128> = (0..32).map(|_| rng.next_u128()).collect();
+    assert_op(
+        &mollusk,
+        Op::CarryCount,
+        &values,
+        ops::carry_count(values.iter().copied()),
+    );
+}
+
+#[test]
+fn mod_pow_matches_host() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    // Each product folded into the result stays below 2^128, so this is
+    // exact. The base's last squaring, 2^64 * 2^64, does wrap to 0, but the
+    // exponent has run out by then and it's never used.
+    assert_op(&mollusk, Op::ModPow, &[2, 127, u128::MAX], 1 << 63);
+
+    let mersenne_127 = (1u128 << 127) - 1;
+    let cases = [
+        [3, 1_000_003, mersenne_127],
+        [u128::MAX, u128::MAX, mersenne_127],
+        [
+            0x1234_5678_9abc_def0_1234_5678_9abc_def0,
+            65537,
+            u128::MAX - 158,
+        ],
+        [7, 0, 1 << 100],
+        [mersenne_127, 5, 1],
+    ];
+    for [base, exp, modulus] in cases {
+        let expected = ops::high_word(ops::mod_pow(base, exp, modulus));
+        assert_op(&mollusk, Op::ModPow, &[base, exp, modulus], expected);
+    }
+
+    assert_op_err(&mollusk, Op::ModPow, &[2, 10, 0], Error::ZeroModulus);
+}
+
+#[test]
+fn full_256_bit_product() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    let mut cases = vec![
+        [u128::MAX, u128::MAX],
+        [u128::MAX, 2],
+        [1 << 127, 1 << 127],
+        [0, 7],
+    ];
+    cases.extend((0..DEFAULT_VECTORS).map(|_| [rng.next_u128(), rng.next_u128()]));
+    for [a, b] in cases {
+        let expected = wide_product_bytes(a, b);
+        let high = u128::from_le_bytes(expected[16..].try_into().unwrap());
+        assert_op_with_output(
+            &mollusk,
+            op_instruction(Op::WideMul, &[a, b]),
+            32,
+            ops::high_word(high),
+            &expected,
+        );
+    }
+    // MAX * MAX = 2^256 - 2^129 + 1
+    assert_eq!(ops::widening_mul(u128::MAX, u128::MAX), (1, u128::MAX - 1));
+}
+
+#[test]
+fn wide_product_as_u64_limbs() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    let mut cases = vec![
+        [u128::MAX, u128::MAX],
+        [1 << 64, 1 << 64],
+        [u64::MAX as u128, 3],
+    ];
+    cases.extend((0..DEFAULT_VECTORS).map(|_| [rng.next_u128(), rng.next_u128()]));
+    for [a, b] in cases {
+        let limbs: Vec<u64> = wide_product_bytes(a, b)
+            .chunks(8)
+            .map(|limb| u64::from_le_bytes(limb.try_into().unwrap()))
+            .collect();
+        assert_eq!(ops::widening_mul_limbs(a, b), limbs[..]);
+
+        let data: Vec<u8> = limbs.iter().flat_map(|limb| limb.to_le_bytes()).collect();
+        assert_op_with_output(
+            &mollusk,
+            op_instruction(Op::WideMulLimbs, &[a, b]),
+            32,
+            limbs[3],
+            &data,
+        );
+    }
+}
+
+#[test]
+fn power_of_two_division_matches_shift() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    let a = rng.next_u128();
+    for k in 0..128 {
+        let b = 1 << k;
+        assert_op(&mollusk, Op::DivShiftMatch, &[a, b], 1);
+        assert_op(&mollusk, Op::Div, &[a, b], ops::high_word(a >> k));
+    }
+
+    for _ in 0..DEFAULT_VECTORS {
+        // Odd and at least 3, so never a power of two, at a random width.
+        let (a, b) = (
+            rng.next_u128(),
+            (rng.next_u128() >> (rng.next_u64() % 128)) | 3,
+        );
+        assert_op(&mollusk, Op::Div, &[a, b], ops::high_word(a / b));
+        let matches = ops::div_shift_match(a, b) as u64;
+        assert_op(&mollusk, Op::DivShiftMatch, &[a, b], matches);
+    }
+    // 6 isn't a power of two, so shifting by its one trailing zero only halves.
+    assert_op(&mollusk, Op::DivShiftMatch, &[600, 6], 0);
+
+    assert_op_err(&mollusk, Op::Div, &[a, 0], Error::DivisionByZero);
+    assert_op_err(&mollusk, Op::DivShiftMatch, &[a, 0], Error::DivisionByZero);
+}
+
+#[test]
+fn product_and_quotient_in_one_run() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let output = || vec![AccountMeta::new(OUT_KEY.into(), false)];
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b) = (
+            rng.next_u128(),
+            rng.next_u128() >> (rng.next_u64() % 128) | 1,
+        );
+        let product = a.wrapping_mul(b);
+        let expected = u128_bytes(&[product, a / b]);
+        assert_op_with_output(
+            &mollusk,
+            op_instruction(Op::MulDiv, &[a, b]),
+            32,
+            ops::high_word(product),
+            &expected,
+        );
+    }
+
+    let mut instruction = op_instruction(Op::MulDiv, &[5, 0]);
+    instruction.accounts = output();
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &[data_account(OUT_KEY, vec![0; 32])],
+        &[Check::instruction_err(InstructionError::Custom(
+            Error::DivisionByZero as u32,
+        ))],
+    );
+}
+
+#[test]
+fn gcd_of_scaled_coprime_pairs() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let assert_gcd = |a: u128, b: u128, expected: u128| {
+        let mut instruction = op_instruction(Op::Gcd, &[a, b]);
+        instruction.accounts = vec![AccountMeta::new(OUT_KEY.into(), false)];
+        let run = run(
+            &mollusk,
+            &instruction,
+            &[data_account(OUT_KEY, vec![0; 16])],
+        )
+        .expect_success();
+        assert_eq!(run.output_u128(0), expected, "gcd({a:#x}, {b:#x})");
+        assert_eq!(run.return_value, Some(ops::high_word(expected)));
+    };
+    assert_gcd(0, 0, 0);
+    assert_gcd(0, u128::MAX, u128::MAX);
+    assert_gcd(u128::MAX, 0, u128::MAX);
+    assert_gcd(u128::MAX, u128::MAX - 1, 1);
+    assert_gcd(1 << 127, 1 << 100, 1 << 100);
+
+    // Consecutive Fibonacci numbers are coprime and take the most steps for
+    // their size, so scaling a pair by `g` gives a long loop ending at `g`.
+    let (mut x, mut y) = (1u128, 2u128);
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    while y < 1 << 46 {
+        let g = rng.next_u128() >> 48 | 1;
+        assert_gcd(x, y, 1);
+        assert_gcd(g * y, g * x, g);
+        (x, y) = (y, x + y);
+    }
+}
+
+#[test]
+fn mul_div_with_256_bit_intermediate() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let assert_mul_div_err = |a: u128, b: u128, c: u128, error: Error| {
+        let mut instruction = op_instruction(Op::MulDivWide, &[a, b, c]);
+        instruction.accounts = vec![AccountMeta::new(OUT_KEY.into(), false)];
+        mollusk.process_and_validate_instruction(
+            &instruction,
+            &[data_account(OUT_KEY, vec![0; 16])],
+            &[Check::instruction_err(InstructionError::Custom(
+                error as u32,
+            ))],
+        );
+    };
+    let assert_mul_div = |a: u128, b: u128, c: u128| {
+        let quotient = ops::mul_div_wide(a, b, c).expect("quotient fits");
+        // `q` is the quotient exactly when `q * c <= a * b < (q + 1) * c`,
+        // compared as 256-bit (high, low) pairs.
+        let flip = |(low, high): (u128, u128)| (high, low);
+        let product = flip(ops::widening_mul(a, b));
+        assert!(
+            flip(ops::widening_mul(quotient, c)) <= product,
+            "{a:#x} * {b:#x} / {c:#x}"
+        );
+        let next = ops::add_wide(ops::widening_mul(quotient, c), (c, 0));
+        assert!(product < flip(next), "{a:#x} * {b:#x} / {c:#x}");
+
+        let instruction = op_instruction(Op::MulDivWide, &[a, b, c]);
+        assert_op_with_output(
+            &mollusk,
+            instruction,
+            16,
+            ops::high_word(quotient),
+            &quotient.to_le_bytes(),
+        );
+    };
+    // The product overflows `u128`, but the quotient fits.
+    assert_mul_div(u128::MAX, u128::MAX, u128::MAX);
+    assert_mul_div(u128::MAX, 1 << 100, 1 << 101);
+    assert_mul_div(u128::MAX, u128::MAX - 1, u128::MAX);
+    assert_mul_div(7, 5, 3);
+    assert_mul_div(0, u128::MAX, 1);
+
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        // With `b` below 2^127 the product's high half is too, so any `c`
+        // of at least 2^127 leaves a quotient that fits.
+        let (a, b) = (rng.next_u128(), rng.next_u128() >> 1);
+        assert_mul_div(a, b, rng.next_u128() | 1 << 127);
+    }
+
+    assert_mul_div_err(5, 7, 0, Error::DivisionByZero);
+    assert_mul_div_err(u128::MAX, 2, 1, Error::Overflow);
+    assert_mul_div_err(1 << 64, 1 << 64, 1, Error::Overflow);
+}
+
+#[test]
+fn mul_mod_prime_matches_host() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let assert_mul_mod = |a: u128, b: u128, p: u128| {
+        let expected = a.wrapping_mul(b) % p;
+        let instruction = op_instruction(Op::MulMod, &[a, b, p]);
+        assert_op_with_output(
+            &mollusk,
+            instruction,
+            16,
+            ops::high_word(expected),
+            &expected.to_le_bytes(),
+        );
+    };
+    // Primes around the `u64` boundary and near the top of `u128`, where
+    // the remainder keeps most of the product's bits.
+    const PRIMES: &[u128] = &[
+        3,
+        (1 << 61) - 1,
+        (1 << 64) - 59,
+        (1 << 64) + 13,
+        (1 << 89) - 1,
+        (1 << 127) - 1,
+        u128::MAX - 158,
+    ];
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for &p in PRIMES {
+        assert_mul_mod(0, u128::MAX, p);
+        assert_mul_mod(p - 1, p - 1, p);
+        assert_mul_mod(u128::MAX, u128::MAX, p);
+        for _ in 0..DEFAULT_VECTORS {
+            assert_mul_mod(rng.next_u128(), rng.next_u128(), p);
+        }
+    }
+
+    let mut instruction = op_instruction(Op::MulMod, &[5, 7, 0]);
+    instruction.accounts = vec![AccountMeta::new(OUT_KEY.into(), false)];
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &[data_account(OUT_KEY, vec![0; 16])],
+        &[Check::instruction_err(InstructionError::Custom(
+            Error::ZeroModulus as u32,
+        ))],
+    );
+}
+
+/// `-n^-1 mod 2^64` for odd `n`, by Newton's iteration: each step doubles
+/// the number of correct low bits, starting from the 3 `n` itself gets right.
+fn montgomery_n_prime(n: u64) -> u64 {
+    let mut inverse = n;
+    for _ in 0..5 {
+        inverse = inverse.wrapping_mul(2u64.wrapping_sub(n.wrapping_mul(inverse)));
+    }
+    inverse.wrapping_neg()
+}
+
+#[test]
+fn montgomery_reduction_matches_host() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let reduce = |t: u128, n: u128, n_prime: u128| {
+        let mut instruction = op_instruction(Op::MontgomeryReduce, &[t, n, n_prime]);
+        instruction.accounts = vec![AccountMeta::new(OUT_KEY.into(), false)];
+        run(
+            &mollusk,
+            &instruction,
+            &[data_account(OUT_KEY, vec![0; 16])],
+        )
+    };
+    let assert_reduce = |t: u128, n: u64| {
+        let n_prime = montgomery_n_prime(n);
+        let expected = ops::montgomery_reduce(t, n as u128, n_prime).expect("valid parameters");
+        // REDC gives `t * R^-1 mod n`: times `R`, it is `t` again.
+        assert!(expected < n, "t = {t:#x}, n = {n:#x}");
+        assert_eq!(
+            ((expected as u128) << 64) % n as u128,
+            t % n as u128,
+            "t = {t:#x}, n = {n:#x}"
+        );
+
+        let run = reduce(t, n as u128, n_prime as u128).expect_success();
+        assert_eq!(run.return_value, Some(expected), "t = {t:#x}, n = {n:#x}");
+        assert_eq!(
+            run.output_u128(0),
+            expected as u128,
+            "t = {t:#x}, n = {n:#x}"
+        );
+    };
+    // Moduli up to `2^64 - 1`, where `t + m * n` carries out of 128 bits.
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for n in [3, (1 << 61) - 1, (1 << 64) - 59, u64::MAX] {
+        assert_reduce(0, n);
+        assert_reduce(((n as u128) << 64) - 1, n);
+        for _ in 0..DEFAULT_VECTORS {
+            assert_reduce(rng.next_u128() % ((n as u128) << 64), n);
+        }
+    }
+
+    let assert_reduce_err = |t: u128, n: u128, n_prime: u128, error: Error| {
+        let result = reduce(t, n, n_prime).result;
+        assert_eq!(
+            result,
+            ProgramResult::Failure(InstructionError::Custom(error as u32)),
+            "t = {t:#x}, n = {n:#x}"
+        );
+    };
+    let n_prime = montgomery_n_prime(97) as u128;
+    assert_reduce_err(5, 0, n_prime, Error::ZeroModulus);
+    assert_reduce_err(5, 98, n_prime, Error::InvalidModulus);
+    assert_reduce_err(5, 97, n_prime + 1, Error::InvalidModulus);
+    assert_reduce_err(5, 97, n_prime | 1 << 64, Error::InvalidModulus);
+    assert_reduce_err(97 << 64, 97, n_prime, Error::InvalidModulus);
+    assert_reduce_err(5, 1 << 64 | 1, n_prime, Error::InvalidModulus);
+}
+
+#[test]
+fn mul_cost_is_operand_independent() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    // Operands at the extremes of each 64-bit limb, where a lowering that
+    // skips zero limbs or short-circuits on small values would take a
+    // different path.
+    let mut operands = vec![
+        (0, 0),
+        (1, 1),
+        (u64::MAX as u128, u64::MAX as u128),
+        (1 << 64, 1 << 64),
+        (u128::MAX, u128::MAX),
+        (u128::MAX, 0),
+    ];
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    operands.extend((0..DEFAULT_VECTORS).map(|_| (rng.next_u128(), rng.next_u128())));
+
+    let mut instruction_units = None;
+    for &(a, b) in &operands {
+        let (c, d) = operands[0];
+        let mut instruction = op_instruction(Op::MulCost, &[a, b, c, d]);
+        instruction.accounts = vec![AccountMeta::new(OUT_KEY.into(), false)];
+        let run = run(
+            &mollusk,
+            &instruction,
+            &[data_account(OUT_KEY, vec![0; 16])],
+        )
+        .expect_success();
+        let costs = run.output_u128(0);
+        let (cost, baseline) = (costs as u64, ops::high_word(costs));
+        println!("a = {a:#x}, b = {b:#x}: {cost} compute units per multiply");
+        assert!(cost > 0, "no compute units measured around the multiply");
+        assert_eq!(
+            run.return_value,
+            Some(0),
+            "{a:#x} * {b:#x} took {cost} units, {c:#x} * {d:#x} {baseline}"
+        );
+
+        // The whole instruction executes the same path too.
+        let units = *instruction_units.get_or_insert(run.compute_units);
+        assert_eq!(
+            run.compute_units, units,
+            "{a:#x} * {b:#x} changed the instruction's compute units"
+        );
+    }
+}
+
+#[test]
+fn multiply_then_divide_recovers_operand() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    assert_op(&mollusk, Op::MulDivIdentity, &[0, 5], 1);
+    assert_op(&mollusk, Op::MulDivIdentity, &[u128::MAX, 1], 1);
+    assert_op(&mollusk, Op::MulDivIdentity, &[u128::MAX / 3, 3], 1);
+    assert_op(&mollusk, Op::MulDivIdentity, &[u128::MAX / 3 + 1, 3], 0);
+    assert_op(&mollusk, Op::MulDivIdentity, &[1 << 64, 1 << 64], 0);
+
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        // Half-width operands never overflow; full-width ones almost always do.
+        let (a, b) = (rng.next_u128(), rng.next_u128() | 1 << 64);
+        for [a, b] in [[a >> 64, b >> 64], [a, b]] {
+            // The identity holds exactly when the product doesn't overflow.
+            let expected = a.checked_mul(b).is_some() as u64;
+            assert_op(&mollusk, Op::MulDivIdentity, &[a, b], expected);
+        }
+    }
+
+    assert_op_err(&mollusk, Op::MulDivIdentity, &[7, 0], Error::DivisionByZero);
+}
+
+#[test]
+fn product_compared_against_threshold() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b) = (rng.next_u128(), rng.next_u128());
+        let product = a.wrapping_mul(b);
+        // Straddle the product, including equality, where `>` must say 0.
+        for t in [product.wrapping_sub(1), product, product.wrapping_add(1)] {
+            let expected = (product > t) as u64;
+            assert_op(&mollusk, Op::MulAbove, &[a, b, t], expected);
+        }
+    }
+    // Only the low half of `(1 << 64) * (1 << 64)` survives: the product
+    // wraps to 0, which exceeds nothing.
+    assert_op(&mollusk, Op::MulAbove, &[1 << 64, 1 << 64, 0], 0);
+    assert_op(&mollusk, Op::MulAbove, &[u128::MAX, 1, u128::MAX - 1], 1);
+    assert_op(&mollusk, Op::MulAbove, &[u128::MAX, 1, u128::MAX], 0);
+}
+
+#[test]
+fn all_ones_identities() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    for index in 0..ops::MAX_IDENTITIES {
+        assert_op_param(&mollusk, Op::MaxIdentity, index, &[u128::MAX], 1);
+    }
+    // `MAX + 1 == 0`, `MAX * MAX == 1` and `MAX * 2 == MAX - 1` don't hold one
+    // below the boundary, so a result of 1 there isn't vacuous.
+    for index in [1, 4, 5] {
+        assert_op_param(&mollusk, Op::MaxIdentity, index, &[u128::MAX - 1], 0);
+        assert_op_param(&mollusk, Op::MaxIdentity, index, &[u64::MAX as u128], 0);
+    }
+
+    mollusk.process_and_validate_instruction(
+        &param_instruction(Op::MaxIdentity, ops::MAX_IDENTITIES, &[u128::MAX]),
+        &[],
+        &[Check::instruction_err(InstructionError::Custom(
+            Error::UnknownOp as u32,
+        ))],
+    );
+}
+
+#[test]
+fn bit_length_of_products() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    assert_op(&mollusk, Op::BitLength, &[0, u128::MAX], 0);
+    assert_op(&mollusk, Op::BitLength, &[1, 1], 1);
+    assert_op(&mollusk, Op::BitLength, &[1 << 63, 1 << 64], 128);
+    assert_op(&mollusk, Op::BitLength, &[1 << 40, 1 << 40], 81);
+    assert_op(
+        &mollusk,
+        Op::BitLength,
+        &[(1 << 64) - 1, (1 << 64) - 1],
+        128,
+    );
+    // Wraps to zero.
+    assert_op(&mollusk, Op::BitLength, &[1 << 64, 1 << 64], 0);
+
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b) = (rng.next_u128() >> 70, rng.next_u128() >> 70);
+        assert_op(&mollusk, Op::BitLength, &[a, b], ops::bit_length(a, b));
+    }
+}
+
+#[test]
+fn multiply_only_on_taken_branch() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b) = (rng.next_u128(), rng.next_u128());
+        let product = ops::high_word(a.wrapping_mul(b));
+        assert_ne!(product, ops::NOT_TAKEN, "pick other operands");
+        for flag in [1, 0x100, u64::MAX] {
+            assert_op_param(&mollusk, Op::MulIf, flag, &[a, b], product);
+        }
+        assert_op_param(&mollusk, Op::MulIf, 0, &[a, b], ops::NOT_TAKEN);
+    }
+}
+
+#[test]
+fn multiply_commutes_and_associates() {
+    // Both laws hold for every operand, so the expected result is always 0:
+    // any other value is an operand-dependent multiply bug.
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    let mut progress = Progress::new("multiply laws", DEFAULT_VECTORS * 4);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b, c) = (rng.next_u128(), rng.next_u128(), rng.next_u128());
+        for operands in [
+            vec![a, b],
+            vec![a >> 64, b],
+            vec![a, b, c],
+            vec![a >> 64, b, c >> 64],
+        ] {
+            progress.record(check_op(&mollusk, Op::MulLaws, 0, &operands, 0));
+        }
+    }
+    progress.finish();
+    assert_op(&mollusk, Op::MulLaws, &[u128::MAX, u128::MAX, u128::MAX], 0);
+    assert_op(&mollusk, Op::MulLaws, &[0, u128::MAX], 0);
+}
+
+#[test]
+fn product_indexes_account_data() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let assert_index = |a: u128, b: u128, len: usize| {
+        let data: Vec<u8> = (0..len).map(|i| ((i * 31) ^ (i >> 8)) as u8).collect();
+        let index = (a.wrapping_mul(b) as u64 % len as u64) as usize;
+        let mut expected = data.clone();
+        expected[index] = !data[index];
+
+        let byte = data[index] as u64;
+        assert_op_with_account(
+            &mollusk,
+            op_instruction(Op::MulIndex, &[a, b]),
+            data,
+            byte,
+            &expected,
+        );
+    };
+    // Products whose high word is set, so only a correct narrowing lands in
+    // bounds at the right byte.
+    assert_index(1 << 64 | 5, 1 << 64 | 3, 256);
+    assert_index(u128::MAX, u128::MAX, 1000);
+    assert_index(u64::MAX as u128, u64::MAX as u128, 10 * 1024);
+    assert_index(7, 11, 1);
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for len in [3, 255, 4096, 10_000] {
+        for _ in 0..DEFAULT_VECTORS {
+            assert_index(rng.next_u128(), rng.next_u128(), len);
+        }
+    }
+
+    let mut instruction = op_instruction(Op::MulIndex, &[7, 11]);
+    instruction.accounts = vec![AccountMeta::new(OUT_KEY.into(), false)];
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &[data_account(OUT_KEY, vec![])],
+        &[Check::instruction_err(InstructionError::Custom(
+            Error::InsufficientInput as u32,
+        ))],
+    );
+}
+
+#[test]
+fn nested_multiply_matches_host() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let assert_nested = |a: u128, b: u128, c: u128, d: u128| {
+        let expected = a.wrapping_mul(b).wrapping_mul(c).wrapping_mul(d);
+        assert_op(
+            &mollusk,
+            Op::MulNested,
+            &[a, b, c, d],
+            ops::high_word(expected),
+        );
+    };
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b, c, d) = (
+            rng.next_u128(),
+            rng.next_u128(),
+            rng.next_u128(),
+            rng.next_u128(),
+        );
+        assert_nested(a, b, c, d);
+        // Operands that fit in 32 bits keep the whole product exact, so a
+        // clobbered intermediate can't hide in bits that wrap away.
+        assert_nested(a >> 96, b >> 96, c >> 96, d >> 96);
+    }
+    assert_nested(u128::MAX, u128::MAX, u128::MAX, u128::MAX);
+    assert_nested(1 << 32, 1 << 32, 1 << 32, 1 << 31);
+    assert_nested(3, 5, 7, 0);
+}
+
+#[test]
+fn recursive_multiply_matches_host() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    // `mul_recursive` unwound into two loops: down through each call's
+    // arguments, then back up through its return.
+    let host_recursive = |a: u128, b: u128, depth: u64| {
+        let mut frames = Vec::new();
+        let (mut a, mut b) = (a, b);
+        for _ in 0..depth {
+            frames.push((a, b));
+            (a, b) = (a.wrapping_mul(b), b.wrapping_add(1));
+        }
+        frames
+            .iter()
+            .rev()
+            .fold(a, |inner, &(a, b)| inner.wrapping_mul(b) ^ a)
+    };
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for depth in [0, 1, 2, 7, ops::MAX_RECURSION_DEPTH] {
+        for _ in 0..4 {
+            let (a, b) = (rng.next_u128(), rng.next_u128());
+            let expected = ops::high_word(host_recursive(a, b, depth));
+            assert_op_param(&mollusk, Op::MulRecursive, depth, &[a, b], expected);
+        }
+    }
+    // The returned product rides in the high word, so any word of it lost
+    // across a return shows.
+    let expected = ops::high_word(host_recursive(u128::MAX, u128::MAX, 8));
+    assert_op_param(
+        &mollusk,
+        Op::MulRecursive,
+        8,
+        &[u128::MAX, u128::MAX],
+        expected,
+    );
+
+    mollusk.process_and_validate_instruction(
+        &param_instruction(Op::MulRecursive, ops::MAX_RECURSION_DEPTH + 1, &[3, 5]),
+        &[],
+        &[Check::instruction_err(InstructionError::Custom(
+            Error::RecursionTooDeep as u32,
+        ))],
+    );
+}
+
+/// `a * b` for little-endian `u64` limb arrays, worked in 32-bit halves
+/// with `u64` intermediates so no `u128` arithmetic is involved.
+fn host_big_mul(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let halves = |limbs: &[u64]| -> Vec<u64> {
+        limbs
+            .iter()
+            .flat_map(|&l| [l & 0xffff_ffff, l >> 32])
+            .collect()
+    };
+    let (a, b) = (halves(a), halves(b));
+    let mut product = vec![0u64; a.len() + b.len()];
+    for (i, &a_i) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &b_j) in b.iter().enumerate() {
+            let sum = a_i * b_j + product[i + j] + carry;
+            product[i + j] = sum & 0xffff_ffff;
+            carry = sum >> 32;
+        }
+        product[i + b.len()] = carry;
+    }
+    product
+        .chunks(2)
+        .map(|pair| pair[0] | pair[1] << 32)
+        .collect()
+}
+
+#[test]
+fn bignum_product_matches_host() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let limb_bytes =
+        |limbs: &[u64]| -> Vec<u8> { limbs.iter().flat_map(|limb| limb.to_le_bytes()).collect() };
+    let assert_big_mul = |a: &[u64], b: &[u64]| {
+        let expected = host_big_mul(a, b);
+        let mut instruction = param_instruction(Op::BigMul, a.len() as u64, &[]);
+        instruction.accounts = [LHS_KEY, RHS_KEY, OUT_KEY]
+            .map(|key| AccountMeta::new(key.into(), false))
+            .to_vec();
+        mollusk.process_and_validate_instruction(
+            &instruction,
+            &[
+                data_account(LHS_KEY, limb_bytes(a)),
+                data_account(RHS_KEY, limb_bytes(b)),
+                data_account(OUT_KEY, vec![0xa5; expected.len() * 8]),
+            ],
+            &[
+                Check::success(),
+                Check::return_data(&expected.last().copied().unwrap_or_default().to_le_bytes()),
+                strict_account_check(OUT_KEY, &limb_bytes(&expected)),
+            ],
+        );
+    };
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    // 128, 256 and 512 bits, and widths that aren't a whole number of `u128`s.
+    for limbs in [1, 2, 3, 4, 7, 8] {
+        let a: Vec<u64> = (0..limbs).map(|_| rng.next_u128() as u64).collect();
+        let b: Vec<u64> = (0..limbs).map(|_| rng.next_u128() as u64).collect();
+        assert_big_mul(&a, &b);
+        // All-ones limbs carry on every step.
+        assert_big_mul(&vec![u64::MAX; limbs], &vec![u64::MAX; limbs]);
+    }
+    assert_big_mul(&[], &[]);
+
+    // An output account too small for the product is rejected.
+    let mut instruction = param_instruction(Op::BigMul, 4, &[]);
+    instruction.accounts = [LHS_KEY, RHS_KEY, OUT_KEY]
+        .map(|key| AccountMeta::new(key.into(), false))
+        .to_vec();
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &[
+            data_account(LHS_KEY, vec![1; 32]),
+            data_account(RHS_KEY, vec![1; 32]),
+            data_account(OUT_KEY, vec![0; 48]),
+        ],
+        &[Check::instruction_err(InstructionError::Custom(
+            Error::InsufficientInput as u32,
+        ))],
+    );
+}
+
+#[test]
+fn sign_extended_i64_multiply() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    // Sign-extend by hand: a negative `a` gets all ones in the upper word.
+    let assert_extended = |a: i64, b: i128| {
+        let upper = if a < 0 { u64::MAX } else { 0 };
+        let extended = (upper as u128) << 64 | a as u64 as u128;
+        let expected = ops::high_word(extended.wrapping_mul(b as u128));
+        assert_op(
+            &mollusk,
+            Op::MulSignExtend,
+            &[a as u64 as u128, b as u128],
+            expected,
+        );
+        // Only the low 64 bits of the first operand are read.
+        let junk = 0x5a5a_5a5a_5a5a_5a5a_u128 << 64;
+        assert_op(
+            &mollusk,
+            Op::MulSignExtend,
+            &[junk | a as u64 as u128, b as u128],
+            expected,
+        );
+    };
+    // -1 * 1 is -1, so the whole upper word must come back as ones.
+    assert_extended(-1, 1);
+    assert_extended(1, -1);
+    assert_extended(-1, 1 << 64);
+    assert_extended(i64::MIN, 1);
+    assert_extended(i64::MIN, i128::MIN);
+    assert_extended(i64::MAX, i128::MAX);
+    assert_extended(-2, i128::MAX);
+    assert_extended(0, -1);
+
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b) = (rng.next_u128() as u64 as i64, rng.next_u128() as i128);
+        assert_extended(a, b);
+        assert_extended(a.wrapping_neg(), b);
+    }
+}
+
+#[test]
+fn multiplicative_hash_matches_host() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let host_hash = |bytes: &[u8]| {
+        let mut hash = ops::HASH_OFFSET_BASIS;
+        for &byte in bytes {
+            hash = hash
+                .wrapping_mul(ops::HASH_PRIME)
+                .wrapping_add(byte as u128);
+        }
+        hash
+    };
+    let assert_hash = |bytes: &[u8]| {
+        let hash = host_hash(bytes);
+        let mut instruction = op_instruction(Op::MulHash, &[]);
+        instruction.data.extend(bytes);
+        assert_op_with_output(
+            &mollusk,
+            instruction,
+            16,
+            ops::high_word(hash),
+            &hash.to_le_bytes(),
+        );
+    };
+    // No bytes leave the offset basis; any length, aligned to 16 or not, is
+    // hashed whole.
+    assert_hash(b"");
+    assert_hash(b"a");
+    assert_hash(b"the quick brown fox jumps over the lazy dog");
+    assert_hash(&[0xff; 255]);
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for len in [1, 15, 16, 17, 100, 1000] {
+        let bytes: Vec<u8> = (0..len).map(|_| rng.next_u128() as u8).collect();
+        assert_hash(&bytes);
+    }
+
+    // Without an account only the high word comes back.
+    let mut instruction = op_instruction(Op::MulHash, &[]);
+    instruction.data.extend(b"abc");
+    assert_instruction(
+        &mollusk,
+        &instruction,
+        &[],
+        ops::high_word(host_hash(b"abc")),
+    );
+}
+
+#[test]
+fn multiply_by_bit_reversed_operand() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    // Reverse bit by bit rather than trusting the host's `reverse_bits`.
+    let reversed = |a: u128| {
+        (0..128)
+            .filter(|bit| a >> bit & 1 == 1)
+            .fold(0u128, |r, bit| r | 1 << (127 - bit))
+    };
+    let assert_reversed = |a: u128| {
+        assert_op(
+            &mollusk,
+            Op::MulReversed,
+            &[a],
+            ops::high_word(reversed(a).wrapping_mul(a)),
+        );
+    };
+    // Palindromes reverse to themselves, so the result is just `a * a`. A
+    // low word ORed with its own reversal, which lands in the high word, is
+    // one.
+    let low = 0x0123_4567_89ab_cdef_u128;
+    for a in [0, u128::MAX, 1 << 127 | 1, low | reversed(low)] {
+        assert_eq!(reversed(a), a, "{a:#x} isn't a palindrome");
+        assert_op(
+            &mollusk,
+            Op::MulReversed,
+            &[a],
+            ops::high_word(a.wrapping_mul(a)),
+        );
+    }
+    // A bit at one end of a word lands at the other end of the other word.
+    for bit in [0, 1, 63, 64, 65, 126, 127] {
+        assert_reversed(1 << bit);
+        assert_reversed(1 << bit | 1);
+    }
+    assert_reversed(u64::MAX as u128);
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        assert_reversed(rng.next_u128());
+    }
+}
+
+#[test]
+fn operand_split_across_data_and_account() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    // The high half of `a` comes from the account and the low half from the
+    // data, whose own high half is filled with junk the program must ignore.
+    let assert_split = |a: u128, b: u128| {
+        let junk = 0xdead_beef_dead_beef_u128 << 64;
+        let instruction = op_instruction(Op::MulSplit, &[junk | a as u64 as u128, b]);
+        let account = |product: u128| {
+            let mut data = ((a >> 64) as u64).to_le_bytes().to_vec();
+            data.extend([0; 8]);
+            data.extend(product.to_le_bytes());
+            data
+        };
+        let product = a.wrapping_mul(b);
+        assert_op_with_account(
+            &mollusk,
+            instruction,
+            account(0),
+            ops::high_word(product),
+            &account(product),
+        );
+    };
+    // Each half alone, then both with the carry between them.
+    assert_split(u64::MAX as u128, 3);
+    assert_split(u128::MAX << 64, 3);
+    assert_split(u128::MAX, u128::MAX);
+    assert_split(1 << 64 | 1, 1 << 63);
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        assert_split(rng.next_u128(), rng.next_u128());
+    }
+
+    // The account has to hold the high half.
+    let mut instruction = op_instruction(Op::MulSplit, &[3, 5]);
+    instruction.accounts = vec![AccountMeta::new(OUT_KEY.into(), false)];
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &[data_account(OUT_KEY, vec![0; 4])],
+        &[Check::instruction_err(InstructionError::Custom(
+            Error::InsufficientInput as u32,
+        ))],
+    );
+}
+
+#[test]
+fn multiply_by_zero_and_one() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let mut operands = vec![
+        0,
+        1,
+        u64::MAX as u128,
+        1 << 64,
+        u128::MAX,
+        u128::MAX - 1,
+        1 << 127,
+    ];
+    // Only high-word bits set, so a multiply that drops the high word shows.
+    operands.extend((0..64).map(|bit| 1u128 << (64 + bit)));
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    operands.extend((0..DEFAULT_VECTORS).map(|_| rng.next_u128()));
+    operands.extend((0..DEFAULT_VECTORS).map(|_| rng.next_u128() & !(u64::MAX as u128)));
+    for a in operands {
+        assert_op(&mollusk, Op::MulIdentities, &[a], 0);
+    }
+}
+
+#[test]
+fn multiply_distributes_over_add() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b, c) = (rng.next_u128(), rng.next_u128(), rng.next_u128());
+        assert_op(&mollusk, Op::MulLaws, &[a, b, c], 0);
+        // `b + c` wraps, so the sum is modulo 2^128 on both sides.
+        assert_op(
+            &mollusk,
+            Op::MulLaws,
+            &[a, u128::MAX - (b >> 1), (b >> 1) + 1 + (c >> 1)],
+            0,
+        );
+    }
+    assert_op(&mollusk, Op::MulLaws, &[u128::MAX, u128::MAX, 1], 0);
+    assert_op(&mollusk, Op::MulLaws, &[1 << 64, u64::MAX as u128, 1], 0);
+}
+
+#[test]
+fn popcount_of_products() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    assert_op(&mollusk, Op::MulPopcount, &[0, u128::MAX], 0);
+    assert_op(&mollusk, Op::MulPopcount, &[u128::MAX, 1], 128);
+    // Bits in only one word.
+    assert_op(&mollusk, Op::MulPopcount, &[u64::MAX as u128, 1], 64);
+    assert_op(&mollusk, Op::MulPopcount, &[u64::MAX as u128, 1 << 64], 64);
+    assert_op(&mollusk, Op::MulPopcount, &[0xff << 64, 1 << 56], 8);
+
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b) = (rng.next_u128(), rng.next_u128());
+        assert_op(
+            &mollusk,
+            Op::MulPopcount,
+            &[a, b],
+            a.wrapping_mul(b).count_ones() as u64,
+        );
+    }
+}
+
+#[test]
+fn crc_of_full_product() {
+    assert_eq!(ops::crc32(b"123456789"), 0xcbf4_3926);
+
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let crc = |a: u128, b: u128| ops::crc32(&a.wrapping_mul(b).to_le_bytes()) as u64;
+    // Products differing only in the low word, which `>> 64` would hide.
+    assert_op(&mollusk, Op::MulCrc, &[3, 5], crc(3, 5));
+    assert_op(&mollusk, Op::MulCrc, &[3, 7], crc(3, 7));
+    assert_ne!(crc(3, 5), crc(3, 7));
+
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b) = (rng.next_u128(), rng.next_u128());
+        assert_op(&mollusk, Op::MulCrc, &[a, b], crc(a, b));
+    }
+}
+
+#[test]
+fn products_checked_against_table_account() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    let pairs: Vec<[u128; 2]> = (0..32)
+        .map(|_| [rng.next_u128(), rng.next_u128()])
+        .collect();
+    let operands: Vec<u128> = pairs.iter().flatten().copied().collect();
+    let mut products: Vec<u128> = pairs.iter().map(|[a, b]| a.wrapping_mul(*b)).collect();
+
+    let mut instruction = op_instruction(Op::CheckTable, &operands);
+    instruction.accounts = vec![AccountMeta::new_readonly(TABLE_KEY.into(), false)];
+    let table = data_account(TABLE_KEY, u128_bytes(&products));
+    assert_instruction(&mollusk, &instruction, &[table], ops::ALL_MATCH);
+
+    products[5] ^= 1 << 64;
+    let table = data_account(TABLE_KEY, u128_bytes(&products));
+    assert_instruction(&mollusk, &instruction, &[table], 5);
+
+    assert_op_err(&mollusk, Op::CheckTable, &operands, Error::MissingAccount);
+}
+
+#[test]
+fn product_of_account_operands_written_to_output_account() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    let mut instruction = op_instruction(Op::MulAccounts, &[]);
+    instruction.accounts = vec![
+        AccountMeta::new_readonly(LHS_KEY.into(), false),
+        AccountMeta::new_readonly(RHS_KEY.into(), false),
+        AccountMeta::new(OUT_KEY.into(), false),
+    ];
+
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b) = (rng.next_u128(), rng.next_u128());
+        let product = a.wrapping_mul(b);
+        let accounts = [
+            data_account(LHS_KEY, u128_bytes(&[a])),
+            data_account(RHS_KEY, u128_bytes(&[b])),
+            data_account(OUT_KEY, vec![0; 16]),
+        ];
+        mollusk.process_and_validate_instruction(
+            &instruction,
+            &accounts,
+            &[
+                Check::success(),
+                Check::return_data(&ops::high_word(product).to_le_bytes()),
+                strict_account_check(OUT_KEY, &product.to_le_bytes()),
+            ],
+        );
+    }
+
+    let accounts = [
+        data_account(LHS_KEY, u128_bytes(&[3])),
+        data_account(RHS_KEY, u128_bytes(&[5])),
+        data_account(OUT_KEY, vec![0; 8]),
+    ];
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &accounts,
+        &[Check::instruction_err(InstructionError::Custom(
+            Error::InsufficientInput as u32,
+        ))],
+    );
+}
+
+#[test]
+fn multiply_chain_intermediates_traced() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let (a, b, iterations, interval) = (3, (1 << 64) | 0x9e37_79b9, 100, 10);
+    let mut expected = Vec::new();
+    ops::mul_trace(a, b, iterations, interval as u64, |_, product| {
+        expected.push(product)
+    });
+
+    let mut instruction = param_instruction(Op::MulTrace, iterations, &[a, b, interval]);
+    instruction.accounts = vec![AccountMeta::new(TRACE_KEY.into(), false)];
+    let trace = data_account(TRACE_KEY, vec![0; expected.len() * 16]);
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &[trace],
+        &[
+            Check::success(),
+            Check::return_data(&(expected.len() as u64).to_le_bytes()),
+            strict_account_check(TRACE_KEY, &u128_bytes(&expected)),
+        ],
+    );
+
+    // Without a trace account the samples are only counted.
+    assert_op_param(&mollusk, Op::MulTrace, iterations, &[a, b, interval], 10);
+}
+
+#[test]
+fn output_account_may_alias_an_input() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let (a, b) = (
+        (0xdead_beef_u128 << 64) | 0x1234_5678,
+        (0x00c0_ffee_u128 << 64) | 0x9abc,
+    );
+
+    // Output over the left operand.
+    let mut instruction = op_instruction(Op::MulAccounts, &[]);
+    instruction.accounts = vec![
+        AccountMeta::new(LHS_KEY.into(), false),
+        AccountMeta::new_readonly(RHS_KEY.into(), false),
+        AccountMeta::new(LHS_KEY.into(), false),
+    ];
+    let accounts = [
+        data_account(LHS_KEY, u128_bytes(&[a])),
+        data_account(RHS_KEY, u128_bytes(&[b])),
+    ];
+    let product = a.wrapping_mul(b);
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &accounts,
+        &[
+            Check::success(),
+            Check::return_data(&ops::high_word(product).to_le_bytes()),
+            strict_account_check(LHS_KEY, &product.to_le_bytes()),
+            strict_account_check(RHS_KEY, &b.to_le_bytes()),
+        ],
+    );
+
+    // One account as both operands and the output: squares in place.
+    instruction.accounts = vec![AccountMeta::new(LHS_KEY.into(), false); 3];
+    let square = a.wrapping_mul(a);
+    mollusk.process_and_validate_instruction(
+        &instruction,
+        &accounts[..1],
+        &[
+            Check::success(),
+            strict_account_check(LHS_KEY, &square.to_le_bytes()),
+        ],
+    );
+}
+
+#[test]
+fn big_mul_rejects_aliased_output() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    // `BigMul` clears its output before reading the inputs, so unlike
+    // `MulAccounts` it can't work in place: output over either operand fails.
+    for keys in [
+        [LHS_KEY, RHS_KEY, LHS_KEY],
+        [LHS_KEY, RHS_KEY, RHS_KEY],
+        [LHS_KEY; 3],
+    ] {
+        let mut instruction = param_instruction(Op::BigMul, 2, &[]);
+        instruction.accounts = keys.map(|key| AccountMeta::new(key.into(), false)).to_vec();
+        mollusk.process_and_validate_instruction(
+            &instruction,
+            &[
+                data_account(LHS_KEY, vec![1; 32]),
+                data_account(RHS_KEY, vec![1; 32]),
+            ],
+            &[Check::instruction_err(InstructionError::Custom(
+                Error::AliasedAccount as u32,
+            ))],
+        );
+    }
+}
+
+#[test]
+fn empty_and_truncated_data_rejected() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let full = op_data(Op::Mul, 0, &[3, 5]);
+    for len in [0, 1, OPERANDS_OFFSET, full.len() - 1] {
+        let instruction = Instruction {
+            program_id: PROGRAM_ID.into(),
+            accounts: vec![],
+            data: full[..len].to_vec(),
+        };
+        mollusk.process_and_validate_instruction(
+            &instruction,
+            &[],
+            &[Check::instruction_err(InstructionError::Custom(
+                Error::InsufficientInput as u32,
+            ))],
+        );
+    }
+}
+
+#[test]
+fn multiply_loop_not_optimized_away() {
+    let objdump = std::env::var("LLVM_OBJDUMP").unwrap_or_else(|_| "llvm-objdump".into());
+    let output = std::process::Command::new(&objdump)
+        .args(["-d", "--no-show-raw-insn"])
+        .arg(format!("{}.so", fixture_path()))
+        .output()
+        .unwrap_or_else(|e| panic!("failed to run {objdump} (set LLVM_OBJDUMP): {e}"));
+    assert!(
+        output.status.success(),
+        "{objdump} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let disasm = String::from_utf8_lossy(&output.stdout);
+
+    if disasm.contains("__multi3") {
+        return;
+    }
+
+    // Without a visible libcall symbol, fall back to the entrypoint's size.
+    let entrypoint_insns = disasm
+        .lines()
+        .skip_while(|line| !line.ends_with("<entrypoint>:"))
+        .skip(1)
+        .take_while(|line| !line.trim().is_empty() && !line.ends_with(">:"))
+        .count();
+    assert!(
+        entrypoint_insns >= MIN_ENTRYPOINT_INSNS,
+        "no call to __multi3 and entrypoint has only {entrypoint_insns} instructions: \
+         the multiply loop was optimized away"
+    );
+}
+
+/// Total memory size of the ELF's `PT_LOAD` segments.
+fn loadable_size(elf: &[u8]) -> u64 {
+    let u16_at = |at: usize| u16::from_le_bytes(elf[at..at + 2].try_into().unwrap()) as usize;
+    let u32_at = |at: usize| u32::from_le_bytes(elf[at..at + 4].try_into().unwrap());
+    let u64_at = |at: usize| u64::from_le_bytes(elf[at..at + 8].try_into().unwrap());
+    assert_eq!(&elf[..4], b"\x7fELF", "not an ELF file");
+
+    let (phoff, phentsize, phnum) = (u64_at(0x20) as usize, u16_at(0x36), u16_at(0x38));
+    (0..phnum)
+        .map(|i| phoff + i * phentsize)
+        .filter(|&header| u32_at(header) == 1)
+        .map(|header| u64_at(header + 0x28))
+        .sum()
+}
+
+#[test]
+fn program_fits_loader_limit() {
+    let path = format!("{}.so", fixture_path());
+    let elf = std::fs::read(&path).unwrap_or_else(|e| panic!("failed to read {path}: {e}"));
+    let size = loadable_size(&elf);
+    // A tighter budget catches a size regression long before deployment would.
+    let budget = env_or("U128_SO_BUDGET", LOADER_LIMIT_BYTES).min(LOADER_LIMIT_BYTES);
+    println!(
+        "{path}: {size} loadable bytes, {} on disk, budget {budget}",
+        elf.len()
+    );
+
+    assert!(
+        size <= budget,
+        "{path} has {size} loadable bytes, over the budget of {budget}"
+    );
+    if size * 100 >= budget * SIZE_WARN_PERCENT {
+        eprintln!(
+            "warning: {path} uses {}% of its {budget}-byte budget",
+            size * 100 / budget
+        );
+    }
+}
+
+/// Operands most `smoke_case`s use: both span more than 64 bits, so their
+/// product exercises every word of the multiply.
+const SMOKE_A: u128 = 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210;
+const SMOKE_B: u128 = 0x9e37_79b9_7f4a_7c15_f39c_c060_5ced_c834;
+
+/// One representative run of `op` for `every_op_smoke`: its instruction
+/// data, the accounts it's given, and what it should return. The match names
+/// every op, so a new one doesn't compile until it has a case.
+fn smoke_case(op: Op) -> (Vec<u8>, Vec<(Pubkey, Account)>, u64) {
+    let (a, b) = (SMOKE_A, SMOKE_B);
+    let out = |len: usize| vec![data_account(OUT_KEY, vec![0; len])];
+    let product = ops::high_word(a.wrapping_mul(b));
+    match op {
+        Op::Mul
+        | Op::And
+        | Op::Or
+        | Op::Xor
+        | Op::Not
+        | Op::SaturatingMul
+        | Op::SaturatingAdd
+        | Op::Average => (op_data(op, 0, &[a, b]), vec![], host_reference(op, a, b)),
+        Op::MixedSignMul => {
+            let signed = (a as i128).wrapping_mul(b as i128) >> 1;
+            (
+                op_data(op, 1, &[a, b]),
+                vec![],
+                ops::mixed_sign_word(signed, a.wrapping_mul(b) >> 1),
+            )
+        }
+        Op::MulSchoolbook | Op::MulIf => (op_data(op, 1, &[a, b]), vec![], product),
+        Op::MulCrc => {
+            let crc = ops::crc32(&a.wrapping_mul(b).to_le_bytes()) as u64;
+            (op_data(op, 0, &[a, b]), vec![], crc)
+        }
+        Op::BitLength => (op_data(op, 0, &[a, b]), vec![], ops::bit_length(a, b)),
+        Op::MulPopcount => (op_data(op, 0, &[a, b]), vec![], ops::popcount(a, b)),
+        Op::MulAbove => (op_data(op, 0, &[a, b, 0]), vec![], 1),
+        Op::MulLaws | Op::MulIdentities => (op_data(op, 0, &[a, b, b]), vec![], 0),
+        Op::MulNested => {
+            let nested = a.wrapping_mul(b).wrapping_mul(a).wrapping_mul(b);
+            (
+                op_data(op, 0, &[a, b, a, b]),
+                vec![],
+                ops::high_word(nested),
+            )
+        }
+        Op::MulRecursive => (
+            op_data(op, 3, &[a, b]),
+            vec![],
+            ops::high_word(ops::mul_recursive(a, b, 3)),
+        ),
+        Op::MulSignExtend => {
+            let extended = (a as u64 as i64 as i128).wrapping_mul(b as i128);
+            (
+                op_data(op, 0, &[a, b]),
+                vec![],
+                ops::high_word(extended as u128),
+            )
+        }
+        Op::MulSplit => {
+            let mut account = (a >> 64).to_le_bytes().to_vec();
+            account.resize(32, 0);
+            (
+                op_data(op, 0, &[a, b]),
+                vec![data_account(OUT_KEY, account)],
+                product,
+            )
+        }
+        Op::MulReversed => (
+            op_data(op, 0, &[a]),
+            vec![],
+            ops::high_word(a.reverse_bits().wrapping_mul(a)),
+        ),
+        Op::MulHash => {
+            let data = op_data(op, 0, &[a, b]);
+            let hash = ops::mul_hash(&data[OPERANDS_OFFSET..]);
+            (data, vec![], ops::high_word(hash))
+        }
+        Op::CarryCount => (op_data(op, 0, &[u128::MAX, 1, u128::MAX]), vec![], 1),
+        Op::ModPow => (
+            op_data(op, 0, &[b, 3, a]),
+            vec![],
+            ops::high_word(ops::mod_pow(b, 3, a)),
+        ),
+        Op::MaxIdentity => (op_data(op, 0, &[u128::MAX]), vec![], 1),
+        Op::Div => (
+            op_data(op, 0, &[a, b >> 64]),
+            vec![],
+            ops::high_word(a / (b >> 64)),
+        ),
+        Op::DivShiftMatch => (op_data(op, 0, &[a, 1 << 70]), vec![], 1),
+        Op::MulDivIdentity => (op_data(op, 0, &[a >> 64, b >> 64]), vec![], 1),
+        Op::DotProduct => {
+            let sum = a.wrapping_mul(b).wrapping_mul(2);
+            (op_data(op, 2, &[a, b, b, a]), vec![], ops::high_word(sum))
+        }
+        Op::DotProductWide => {
+            let (_, high) = ops::widening_mul(a, b);
+            (op_data(op, 1, &[a, b]), out(32), ops::high_word(high))
+        }
+        Op::MulTrace => (op_data(op, 100, &[a, b, 10]), vec![], 10),
+        Op::CheckTable => {
+            let table = data_account(TABLE_KEY, u128_bytes(&[a.wrapping_mul(b)]));
+            (op_data(op, 0, &[a, b]), vec![table], ops::ALL_MATCH)
+        }
+        Op::BigMul => {
+            let accounts = vec![
+                data_account(LHS_KEY, u128_bytes(&[a])),
+                data_account(RHS_KEY, u128_bytes(&[b])),
+                data_account(OUT_KEY, vec![0; 32]),
+            ];
+            (
+                op_data(op, 2, &[]),
+                accounts,
+                ops::high_word(ops::widening_mul(a, b).1),
+            )
+        }
+        Op::MulAccounts => {
+            let accounts = vec![
+                data_account(LHS_KEY, u128_bytes(&[a])),
+                data_account(RHS_KEY, u128_bytes(&[b])),
+                data_account(OUT_KEY, vec![0; 16]),
+            ];
+            (op_data(op, 0, &[]), accounts, product)
+        }
+        Op::HexMul => {
+            let mut data = op_data(op, 0, &[]);
+            data.extend(format!("{a:032x}{b:032x}").as_bytes());
+            (data, vec![], product)
+        }
+        Op::MulUnaligned => {
+            let mut data = op_data(op, 0, &[]);
+            data.resize(UNALIGNED_OPERANDS_OFFSET, 0);
+            data.extend(u128_bytes(&[a, b]));
+            (data, vec![], product)
+        }
+        Op::WideSum => {
+            let mut data = op_data(op, 2, &[u128::MAX, u128::MAX]);
+            data[WIDTH_OFFSET] = 16;
+            (data, out(32), 1)
+        }
+        Op::WideMul => (
+            op_data(op, 0, &[a, b]),
+            out(32),
+            ops::high_word(ops::widening_mul(a, b).1),
+        ),
+        Op::MulDiv => (op_data(op, 0, &[a, b]), out(32), product),
+        Op::WideMulLimbs => (
+            op_data(op, 0, &[a, b]),
+            out(32),
+            ops::widening_mul_limbs(a, b)[3],
+        ),
+        Op::Gcd => (op_data(op, 0, &[12 << 64, 18 << 64]), out(16), 6),
+        Op::MulDivWide => (op_data(op, 0, &[a, b, b]), out(16), ops::high_word(a)),
+        Op::MulMod => (
+            op_data(op, 0, &[a, b, a | 1]),
+            out(16),
+            ops::high_word(ops::mul_mod(a, b, a | 1)),
+        ),
+        Op::MontgomeryReduce => {
+            let n = SMOKE_B as u64 | 1;
+            let n_prime = montgomery_n_prime(n);
+            let reduced = ops::montgomery_reduce(a >> 64, n as u128, n_prime).unwrap();
+            (
+                op_data(op, 0, &[a >> 64, n as u128, n_prime as u128]),
+                out(16),
+                reduced,
+            )
+        }
+        Op::MulCost => (op_data(op, 0, &[a, b, b, a]), out(16), 0),
+        Op::MulCount => (op_data(op, 4, &[a, b]), out(32), 4),
+        Op::MulStruct => {
+            let mut record = u128_bytes(&[a, b]);
+            record.resize(64, 0);
+            (
+                op_data(op, 0, &[]),
+                vec![data_account(OUT_KEY, record)],
+                product,
+            )
+        }
+        Op::MulIndex => {
+            let data = (0..64).collect();
+            let index = a.wrapping_mul(b) as u64 % 64;
+            (
+                op_data(op, 0, &[a, b]),
+                vec![data_account(OUT_KEY, data)],
+                index,
+            )
+        }
+    }
+}
+
+/// Every opcode, once each against its `smoke_case`, printed as a pass/fail
+/// line per op. A quick check that a toolchain handles the whole range of
+/// ops before running the deeper tests; `cargo xtask crosscheck --all-ops`
+/// runs it.
+#[test]
+fn every_op_smoke() {
+    let mollusk = Mollusk::new(&PROGRAM_ID.into(), &fixture_path());
+    let mut failed = Vec::new();
+    for op in (0..=u8::MAX).filter_map(Op::from_u8) {
+        let (data, accounts, expected) = smoke_case(op);
+        let instruction = Instruction {
+            program_id: PROGRAM_ID.into(),
+            accounts: accounts
+                .iter()
+                .map(|(key, _)| AccountMeta::new(*key, false))
+                .collect(),
+            data,
+        };
+        let run = run(&mollusk, &instruction, &accounts);
+        if run.result.is_ok() && run.return_value == Some(expected) {
+            println!("{SMOKE_PREFIX} {op:?} ok");
+        } else {
+            let actual = format!("{:?} returning {:x?}", run.result, run.return_value);
+            println!("{SMOKE_PREFIX} {op:?} FAILED ({actual}, expected {expected:#x})");
+            failed.push(format!("{op:?}"));
+        }
+    }
+    assert!(
+        failed.is_empty(),
+        "smoke test failed for: {}",
+        failed.join(", ")
+    );
+}
@@ -0,0 +1,127 @@
+//! The mollusk tests' core checks, rerun under `solana-program-test`'s
+//! BanksClient runtime. It executes through a full bank rather than
+//! mollusk's direct invocation, so a result that holds in both isn't an
+//! artifact of either harness. Enabled with `--features program-test`.
+
+use super::{
+    fixture_path, host_reference, op_data, SplitMix64, DEFAULT_SEED, DEFAULT_VECTORS, PROGRAM_ID,
+};
+use crate::ops::{self, Op};
+use crate::Error;
+use solana_account::Account;
+use solana_instruction::{error::InstructionError, Instruction};
+use solana_program_test::{ProgramTest, ProgramTestContext};
+use solana_rent::Rent;
+use solana_signer::Signer;
+use solana_transaction::Transaction;
+use solana_transaction_error::TransactionError;
+
+/// Start a bank with the built program deployed at `PROGRAM_ID`, the way
+/// `ProgramTest::add_program` deploys a prebuilt `.so`. Loading it by path
+/// keeps the artifact the mollusk tests use, rather than `ProgramTest`'s own
+/// search for `<name>.so`.
+async fn start() -> ProgramTestContext {
+    let path = format!("{}.so", fixture_path());
+    let elf = std::fs::read(&path).unwrap_or_else(|err| panic!("failed to read {path}: {err}"));
+    let mut program_test = ProgramTest::default();
+    program_test.add_account(
+        PROGRAM_ID.into(),
+        Account {
+            lamports: Rent::default().minimum_balance(elf.len()).max(1),
+            data: elf,
+            owner: solana_sdk_ids::bpf_loader::id(),
+            executable: true,
+            rent_epoch: 0,
+        },
+    );
+    program_test.start_with_context().await
+}
+
+/// Send `data` to the program in its own transaction, returning the
+/// transaction's result and the program's return data.
+async fn run(
+    context: &mut ProgramTestContext,
+    data: Vec<u8>,
+) -> (Result<(), TransactionError>, Vec<u8>) {
+    let instruction = Instruction {
+        program_id: PROGRAM_ID.into(),
+        accounts: vec![],
+        data,
+    };
+    let transaction = Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&context.payer.pubkey()),
+        &[&context.payer],
+        context.last_blockhash,
+    );
+    let outcome = context
+        .banks_client
+        .process_transaction_with_metadata(transaction)
+        .await
+        .expect("BanksClient transport failed");
+    let returned = outcome
+        .metadata
+        .and_then(|metadata| metadata.return_data)
+        .map(|return_data| return_data.data)
+        .unwrap_or_default();
+    (outcome.result, returned)
+}
+
+/// Run `op` with `param` and assert it succeeds with `expected` as its
+/// return data.
+async fn assert_op_param(
+    context: &mut ProgramTestContext,
+    op: Op,
+    param: u64,
+    operands: &[u128],
+    expected: u64,
+) {
+    let (result, returned) = run(context, op_data(op, param, operands)).await;
+    assert_eq!(result, Ok(()), "{op:?} {operands:x?} failed");
+    assert_eq!(
+        returned,
+        expected.to_le_bytes(),
+        "{op:?} {operands:x?} returned the wrong value"
+    );
+}
+
+// Each transaction in a test must differ, or the bank rejects the repeat
+// as already processed.
+
+#[tokio::test]
+async fn seeded_operands_match_host() {
+    let mut context = start().await;
+    let mut rng = SplitMix64(DEFAULT_SEED);
+    for _ in 0..DEFAULT_VECTORS {
+        let (a, b) = (rng.next_u128(), rng.next_u128());
+        for &op in Op::BINARY {
+            assert_op_param(&mut context, op, 0, &[a, b], host_reference(op, a, b)).await;
+        }
+    }
+}
+
+#[tokio::test]
+async fn multiply_loop_matches_host() {
+    let mut context = start().await;
+    let (a, b) = (
+        0x0123_4567_89ab_cdef_fedc_ba98_7654_3210,
+        0x9e37_79b9_7f4a_7c15,
+    );
+    for iterations in [1, 10, 100] {
+        let expected = ops::high_word(ops::mul_loop(a, b, iterations));
+        assert_op_param(&mut context, Op::Mul, iterations, &[a, b], expected).await;
+    }
+}
+
+#[tokio::test]
+async fn truncated_data_rejected() {
+    let mut context = start().await;
+    let (result, _) = run(&mut context, op_data(Op::Mul, 0, &[3])).await;
+    assert_eq!(
+        result,
+        Err(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(Error::InsufficientInput as u32)
+        ))
+    );
+}
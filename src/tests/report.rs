@@ -0,0 +1,10 @@
+//! Prefixes of the lines the tests print for xtask to parse. xtask includes
+//! this file as well (`mod report` in `xtask/src/main.rs`), so the printing
+//! and the parsing sides can't drift apart.
+
+/// Prefix of the lines `compute_units_per_op` prints, which `cargo xtask
+/// cu-compare` parses.
+pub const CU_REPORT_PREFIX: &str = "compute units:";
+/// Prefix of the lines `every_op_smoke` prints, which `cargo xtask
+/// stack-sweep` parses.
+pub const SMOKE_PREFIX: &str = "smoke:";
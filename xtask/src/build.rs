@@ -0,0 +1,797 @@
+//! Building the BPF program, with the exact configuration it was built with
+//! resolved up front so it can be printed and recorded.
+
+use crate::manifest::{self, Manifest};
+use crate::{nm_symbols, toolchain_dir_of_linker, BuildArgs};
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{BufRead, BufReader, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+pub const TARGET: &str = "bpfel-unknown-none";
+const TOOLCHAIN: &str = "+nightly";
+const PROFILE: &str = "release";
+/// Profiles cargo defines without any `[profile.*]` section.
+const BUILTIN_PROFILES: &[&str] = &["dev", "release", "test", "bench"];
+const BUILD_STD: &str = "-Zbuild-std=core,alloc";
+/// Package name of the fixture program in the project root.
+const FIXTURE_PACKAGE: &str = "upstream-u128-test";
+/// Directory of standalone example programs, one crate per subdirectory.
+const PROGRAMS_DIR: &str = "programs";
+/// compiler-builtins' 128-bit multiply, which the fixture's `u128`
+/// multiplies are expected to call.
+pub const MUL_LIBCALL: &str = "__multi3";
+/// Where `cargo build --timings` writes its HTML report.
+const TIMINGS_REPORT: &str = "target/cargo-timings/cargo-timing.html";
+
+/// Everything that determines how the BPF program is compiled and linked.
+pub struct BuildConfig {
+    /// `bpfel-unknown-none`, or the absolute path of a custom target spec
+    pub target: String,
+    pub profile: String,
+    /// rustflags passed to the build, after CLI overrides are applied
+    pub rustflags: Vec<String>,
+    /// Where the base rustflags were read from
+    pub rustflags_source: String,
+    /// `(flag, option)` pairs for flags set or replaced by CLI options
+    pub overrides: Vec<(String, String)>,
+    /// Run cargo verbosely and have it write a timings report
+    pub verbose: bool,
+    /// The one package to build, or `None` for the fixture and every program
+    /// in `programs/`
+    pub package: Option<String>,
+    /// `--features` and `--no-default-features` arguments passed to cargo
+    pub feature_args: Vec<String>,
+}
+
+impl BuildConfig {
+    /// Resolve the rustflags cargo would use for the BPF target, following
+    /// cargo's precedence: `CARGO_ENCODED_RUSTFLAGS`, then `RUSTFLAGS`, then
+    /// `CARGO_TARGET_BPFEL_UNKNOWN_NONE_RUSTFLAGS`, then the project's
+    /// `.cargo/config.toml`.
+    pub fn resolve(project_root: &Path) -> Result<Self> {
+        let env = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let (rustflags, rustflags_source) = if let Some(flags) = env("CARGO_ENCODED_RUSTFLAGS") {
+            let flags = flags.split('\x1f').map(String::from).collect();
+            (flags, "env CARGO_ENCODED_RUSTFLAGS".to_string())
+        } else if let Some(flags) = env("RUSTFLAGS") {
+            (split_flags(&flags), "env RUSTFLAGS".to_string())
+        } else if let Some(flags) = env("CARGO_TARGET_BPFEL_UNKNOWN_NONE_RUSTFLAGS") {
+            let source = "env CARGO_TARGET_BPFEL_UNKNOWN_NONE_RUSTFLAGS";
+            (split_flags(&flags), source.to_string())
+        } else {
+            let config_path = project_root.join(".cargo/config.toml");
+            (
+                config_rustflags(&config_path)?,
+                config_path.display().to_string(),
+            )
+        };
+
+        Ok(Self {
+            target: TARGET.to_string(),
+            profile: PROFILE.to_string(),
+            rustflags,
+            rustflags_source,
+            overrides: Vec::new(),
+            verbose: false,
+            package: None,
+            feature_args: Vec::new(),
+        })
+    }
+
+    /// Apply `--deny-warnings`: fail the build on any compiler warning.
+    pub fn deny_warnings(&mut self) {
+        self.rustflags
+            .extend(["-D".to_string(), "warnings".to_string()]);
+        self.overrides
+            .push(("-D warnings".to_string(), "--deny-warnings".to_string()));
+    }
+
+    /// Apply `--verbose-rustc`: verbose cargo output with a `--timings`
+    /// report, plus rustc's per-pass timings via `-Ztime-passes`.
+    pub fn enable_verbose_rustc(&mut self) {
+        self.verbose = true;
+        let flag = "-Ztime-passes".to_string();
+        if !self.rustflags.contains(&flag) {
+            self.rustflags.push(flag.clone());
+        }
+        self.overrides.push((flag, "--verbose-rustc".to_string()));
+    }
+
+    /// Apply `--target-json`: build for the custom target spec at `path`.
+    /// The rustflags resolved for `bpfel-unknown-none` still apply, since
+    /// they are passed to cargo explicitly.
+    pub fn use_target_json(&mut self, path: &Path) -> Result<()> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read target spec {}", path.display()))?;
+        if let Err(err) = check_json_object(&contents) {
+            bail!("{} is not a valid target spec: {err}", path.display());
+        }
+        // Cargo runs from the project root, so a relative path would resolve
+        // against the wrong directory.
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("failed to resolve {}", path.display()))?;
+        self.target = path.display().to_string();
+        self.overrides.push((
+            format!("--target={}", self.target),
+            "--target-json".to_string(),
+        ));
+        Ok(())
+    }
+
+    /// Apply `--profile`: build with cargo profile `name`, which must be
+    /// built in or defined in the project's `Cargo.toml`.
+    pub fn use_profile(&mut self, project_root: &Path, name: &str) -> Result<()> {
+        if !BUILTIN_PROFILES.contains(&name) {
+            let manifest_path = project_root.join("Cargo.toml");
+            let manifest: toml::Table = fs::read_to_string(&manifest_path)
+                .with_context(|| format!("failed to read {}", manifest_path.display()))?
+                .parse()
+                .with_context(|| format!("failed to parse {}", manifest_path.display()))?;
+            let defined: Vec<&str> = manifest
+                .get("profile")
+                .and_then(toml::Value::as_table)
+                .map(|profiles| profiles.keys().map(String::as_str).collect())
+                .unwrap_or_default();
+            if !defined.contains(&name) {
+                bail!(
+                    "no profile `{name}` in {} (built in: {}; defined: {})",
+                    manifest_path.display(),
+                    BUILTIN_PROFILES.join(", "),
+                    if defined.is_empty() {
+                        "none".to_string()
+                    } else {
+                        defined.join(", ")
+                    }
+                );
+            }
+        }
+        self.profile = name.to_string();
+        self.overrides
+            .push((format!("--profile={name}"), "--profile".to_string()));
+        Ok(())
+    }
+
+    /// Apply `--program`: build only the program in `programs/<name>`.
+    pub fn use_program(&mut self, project_root: &Path, name: &str) -> Result<()> {
+        let programs = programs(project_root)?;
+        let Some((_, package)) = programs.iter().find(|(dir, _)| dir == name) else {
+            let names: Vec<&str> = programs.iter().map(|(dir, _)| dir.as_str()).collect();
+            bail!(
+                "no program `{name}` in {PROGRAMS_DIR}/ (found: {})",
+                if names.is_empty() {
+                    "none".to_string()
+                } else {
+                    names.join(", ")
+                }
+            );
+        };
+        self.package = Some(package.clone());
+        self.overrides
+            .push((format!("--package={package}"), "--program".to_string()));
+        Ok(())
+    }
+
+    /// Apply `--features` and `--no-default-features`, passed through to
+    /// cargo, e.g. to build only some opcode groups of the fixture.
+    pub fn use_features(&mut self, features: Option<&str>, no_default_features: bool) {
+        if no_default_features {
+            let flag = "--no-default-features".to_string();
+            self.feature_args.push(flag.clone());
+            self.overrides.push((flag.clone(), flag));
+        }
+        if let Some(features) = features {
+            let flag = format!("--features={features}");
+            self.feature_args.push(flag.clone());
+            self.overrides.push((flag, "--features".to_string()));
+        }
+    }
+
+    /// Where this build leaves the linked program: the one `--program`
+    /// selected, or otherwise the fixture. Cargo names the output
+    /// directory of a custom target after the spec's file stem.
+    pub fn artifact(&self, project_root: &Path) -> PathBuf {
+        let target = Path::new(&self.target);
+        let target_dir = match target.extension() {
+            Some(ext) if ext == "json" => target.file_stem().unwrap_or_default(),
+            _ => target.as_os_str(),
+        };
+        project_root
+            .join("target")
+            .join(target_dir)
+            .join(profile_dir(&self.profile))
+            .join(artifact_name(
+                self.package.as_deref().unwrap_or(FIXTURE_PACKAGE),
+            ))
+    }
+
+    /// The value of each `-C` codegen option, e.g. `linker=...`, in order.
+    fn codegen_options(&self) -> Vec<&str> {
+        let mut options = Vec::new();
+        let mut flags = self.rustflags.iter().map(String::as_str);
+        while let Some(flag) = flags.next() {
+            let value = match flag {
+                "-C" => flags.next().unwrap_or_default(),
+                _ => flag.strip_prefix("-C").unwrap_or_default(),
+            };
+            options.push(value);
+        }
+        options
+    }
+
+    /// The linker named by a `-C linker=...` flag, if any.
+    pub fn linker(&self) -> Option<&str> {
+        self.codegen_options()
+            .into_iter()
+            .find_map(|option| option.strip_prefix("linker="))
+    }
+
+    /// The arguments passed to the linker by `-C link-arg=...` flags.
+    pub fn link_args(&self) -> Vec<&str> {
+        self.codegen_options()
+            .into_iter()
+            .filter_map(|option| option.strip_prefix("link-arg="))
+            .collect()
+    }
+
+    /// Arguments to `cargo` for this build.
+    pub fn cargo_args(&self) -> Vec<String> {
+        let mut args = vec![
+            TOOLCHAIN.to_string(),
+            "build".to_string(),
+            format!("--profile={}", self.profile),
+            format!("--target={}", self.target),
+            BUILD_STD.to_string(),
+        ];
+        match &self.package {
+            Some(package) => args.push(format!("--package={package}")),
+            // Everything in the workspace except xtask itself.
+            None => args.extend(["--workspace".to_string(), "--exclude=xtask".to_string()]),
+        }
+        args.extend(self.feature_args.iter().cloned());
+        if self.verbose {
+            args.push("-v".to_string());
+            args.push("--timings".to_string());
+        }
+        args
+    }
+
+    /// The `cargo` invocation for this build. The rustflags are passed
+    /// explicitly so the build uses exactly what was resolved and recorded.
+    pub fn command(&self, project_root: &Path) -> Command {
+        let mut cmd = Command::new("cargo");
+        cmd.args(self.cargo_args())
+            .env("CARGO_ENCODED_RUSTFLAGS", self.rustflags.join("\x1f"))
+            .env_remove("RUSTFLAGS")
+            .current_dir(project_root);
+        cmd
+    }
+
+    pub fn print(&self, log: &dyn Fn(&str)) {
+        log("Build configuration:");
+        log(&format!(
+            "  command:   cargo {}",
+            self.cargo_args().join(" ")
+        ));
+        log(&format!("  target:    {}", self.target));
+        log(&format!("  profile:   {}", self.profile));
+        log(&format!(
+            "  linker:    {}",
+            self.linker().unwrap_or("(default)")
+        ));
+        log(&format!("  rustflags: (from {})", self.rustflags_source));
+        for flag in &self.rustflags {
+            log(&format!("    {flag}"));
+        }
+        for (flag, option) in &self.overrides {
+            log(&format!("  override:  {flag} (from {option})"));
+        }
+    }
+
+    /// The configuration as a JSON object, for `build --record`.
+    pub fn to_json(&self) -> String {
+        let list = |items: &[String]| {
+            let items: Vec<String> = items.iter().map(|item| json_string(item)).collect();
+            format!("[{}]", items.join(", "))
+        };
+        let mut command = vec!["cargo".to_string()];
+        command.extend(self.cargo_args());
+        let overrides: Vec<String> = self
+            .overrides
+            .iter()
+            .map(|(flag, option)| {
+                format!(
+                    "{{\"flag\": {}, \"option\": {}}}",
+                    json_string(flag),
+                    json_string(option)
+                )
+            })
+            .collect();
+        format!(
+            "{{\n  \"command\": {},\n  \"target\": {},\n  \"profile\": {},\n  \"linker\": {},\n  \
+             \"rustflags\": {},\n  \"rustflags_source\": {},\n  \"overrides\": [{}]\n}}\n",
+            list(&command),
+            json_string(&self.target),
+            json_string(&self.profile),
+            self.linker()
+                .map(json_string)
+                .unwrap_or_else(|| "null".to_string()),
+            list(&self.rustflags),
+            json_string(&self.rustflags_source),
+            overrides.join(", "),
+        )
+    }
+}
+
+/// Build the BPF program, returning the path of the linked `.so`. With
+/// `--stdout`, progress messages and the child's stdout are sent to stderr so
+/// stdout stays free for piped artifacts.
+pub fn build_project(project_root: &Path, args: &BuildArgs) -> Result<PathBuf> {
+    let to_stderr = args.stdout.is_some();
+    let log = |msg: &str| {
+        if to_stderr {
+            eprintln!("{msg}");
+        } else {
+            println!("{msg}");
+        }
+    };
+
+    let mut config = BuildConfig::resolve(project_root)?;
+    if args.verbose_rustc {
+        config.enable_verbose_rustc();
+    }
+    if args.deny_warnings {
+        config.deny_warnings();
+    }
+    if let Some(path) = &args.target_json {
+        config.use_target_json(path)?;
+    }
+    if let Some(profile) = &args.profile {
+        config.use_profile(project_root, profile)?;
+    }
+    if let Some(program) = &args.program {
+        config.use_program(project_root, program)?;
+    }
+    config.use_features(args.features.as_deref(), args.no_default_features);
+    config.print(&log);
+    if let Some(path) = &args.record {
+        fs::write(path, config.to_json())
+            .with_context(|| format!("failed to write build record to {}", path.display()))?;
+        log(&format!("  build record written to {}", path.display()));
+    }
+
+    check_toolchain(&config)?;
+    check_build_std()?;
+    // A linker that can't be run fails the build below with a clearer error.
+    if let Ok(checks) = check_link_args(&config) {
+        for (arg, check) in checks {
+            match check {
+                LinkArgCheck::Unknown(problem) => log(&format!(
+                    "warning: link arg `{arg}` will likely fail the build: {problem}"
+                )),
+                LinkArgCheck::Unchecked(reason) => {
+                    log(&format!("note: link arg `{arg}` not checked: {reason}"))
+                }
+                LinkArgCheck::Ok => {}
+            }
+        }
+    }
+
+    log("Building project with cargo +nightly...");
+    let mut cmd = config.command(project_root);
+    if to_stderr {
+        cmd.stdout(std::io::stderr());
+    }
+    // Cargo's stderr is piped through to count warnings, so ask for the
+    // colors it would have used on the terminal.
+    if std::io::stderr().is_terminal() {
+        cmd.arg("--color=always");
+    }
+    cmd.stderr(Stdio::piped());
+    let mut child = cmd.spawn().context("failed to run: build project")?;
+    let warnings = forward_counting_warnings(child.stderr.take().expect("stderr is piped"))?;
+    let status = child.wait().context("failed to run: build project")?;
+    match warnings {
+        0 => log("No compiler warnings."),
+        1 => log("1 compiler warning (see above)."),
+        n => log(&format!("{n} compiler warnings (see above).")),
+    }
+    if !status.success() {
+        bail!("command failed: build project");
+    }
+    log("Build complete!");
+    if config.verbose {
+        log(&format!(
+            "Timings report: {}",
+            project_root.join(TIMINGS_REPORT).display()
+        ));
+    }
+    let artifact = config.artifact(project_root);
+    // Without `nm` there's nothing to check against; the build itself worked.
+    if let Ok(symbols) = nm_symbols(&artifact, false) {
+        if !symbols.iter().any(|(_, name)| name == MUL_LIBCALL) {
+            log(&format!(
+                "warning: {} doesn't contain `{MUL_LIBCALL}`: every 128-bit multiply was inlined \
+                 or lowered some other way. Compare its disassembly with a known-good build \
+                 (`cargo xtask asm-diff`) before trusting the results.",
+                artifact.display()
+            ));
+        }
+    }
+    Ok(artifact)
+}
+
+/// Copy `stderr` to our stderr line by line, returning how many compiler
+/// warnings went past. Cargo's own `generated N warnings` summaries and its
+/// non-compiler warnings (config, manifest) aren't counted.
+fn forward_counting_warnings(stderr: impl Read) -> Result<usize> {
+    let mut warnings = 0;
+    for line in BufReader::new(stderr).lines() {
+        let line = line.context("failed to read build output")?;
+        eprintln!("{line}");
+        let plain = strip_ansi(&line);
+        if let Some(message) = plain.strip_prefix("warning: ") {
+            let summary = message.contains(" generated ") && message.contains(" warning");
+            let from_cargo = message.starts_with("unused config key")
+                || message.contains("manifest")
+                || message.starts_with("profiles for the non root package");
+            if !summary && !from_cargo {
+                warnings += 1;
+            }
+        }
+    }
+    Ok(warnings)
+}
+
+/// `line` without ANSI color escapes.
+fn strip_ansi(line: &str) -> String {
+    let mut plain = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI sequences end at the first letter.
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+/// Check the toolchain the configured linker belongs to: fail if it was
+/// built on another platform, and warn when the linker was built against a
+/// different LLVM than the one now installed in it, or an older LLVM than
+/// `rustc` emits bitcode for. Each otherwise shows up as an inscrutable
+/// failure deep in the build.
+fn check_toolchain(config: &BuildConfig) -> Result<()> {
+    let Some(toolchain_dir) = config
+        .linker()
+        .and_then(|l| toolchain_dir_of_linker(Path::new(l)))
+    else {
+        return Ok(());
+    };
+    let Some(manifest) = Manifest::load(&toolchain_dir)? else {
+        return Ok(());
+    };
+    manifest.check_host(&toolchain_dir)?;
+    let Some(linker_llvm) = manifest.get("linker_llvm_version") else {
+        return Ok(());
+    };
+
+    let mut problems = Vec::new();
+    if let Some(installed) = manifest.get("llvm_version") {
+        if installed != linker_llvm {
+            problems.push(format!(
+                "the linker was built against LLVM {linker_llvm}, but LLVM {installed} is \
+                 installed now; re-run `cargo xtask build-linker`"
+            ));
+        }
+    }
+    let rustc_llvm = manifest::rustc_llvm_version(TOOLCHAIN);
+    let rustc_major = rustc_llvm.as_deref().and_then(manifest::llvm_major);
+    if let (Some(rustc), Some(linker)) = (rustc_major, manifest::llvm_major(linker_llvm)) {
+        if rustc > linker {
+            problems.push(format!(
+                "rustc {TOOLCHAIN} emits LLVM {} bitcode, newer than the linker's LLVM \
+                 {linker_llvm}; rebuild with `cargo xtask build-llvm` and \
+                 `cargo xtask build-linker` from a newer LLVM branch",
+                rustc_llvm.as_deref().unwrap_or_default()
+            ));
+        }
+    }
+
+    for problem in problems {
+        eprintln!("==========================================");
+        eprintln!("warning: LLVM version mismatch: {problem}");
+        eprintln!("==========================================");
+    }
+    Ok(())
+}
+
+/// Fail unless rustc `TOOLCHAIN` has the standard library sources that
+/// `BUILD_STD` compiles `core` and `alloc` from. Without them cargo stops
+/// with a lockfile error that doesn't say what's missing. If rustc itself
+/// can't be run, the build fails below with a clearer error.
+fn check_build_std() -> Result<()> {
+    let Ok(output) = Command::new("rustc")
+        .args([TOOLCHAIN, "--print", "sysroot"])
+        .output()
+    else {
+        return Ok(());
+    };
+    if !output.status.success() {
+        return Ok(());
+    }
+    let sysroot = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    let library = sysroot.join("lib/rustlib/src/rust/library");
+    if !library.join("core/Cargo.toml").exists() || !library.join("alloc/Cargo.toml").exists() {
+        bail!(
+            "rustc {TOOLCHAIN} has no standard library sources in {}, which `{BUILD_STD}` \
+             needs to build `core` and `alloc` for {TARGET}; install them with \
+             `rustup component add rust-src --toolchain {}`",
+            library.display(),
+            TOOLCHAIN.trim_start_matches('+')
+        );
+    }
+    Ok(())
+}
+
+/// Whether the linker accepts a link arg from the build's rustflags.
+pub enum LinkArgCheck {
+    Ok,
+    /// The linker (or its LLVM, for `--llvm-args`) has no such option
+    Unknown(String),
+    /// There was nothing to check it against
+    Unchecked(String),
+}
+
+/// Check each `-C link-arg` against the options the linker's `--help` lists,
+/// and each option given to `--llvm-args` against those of the LLVM in the
+/// linker's cached toolchain, as its `llc` lists them.
+pub fn check_link_args(config: &BuildConfig) -> Result<Vec<(String, LinkArgCheck)>> {
+    let Some(linker) = config.linker() else {
+        bail!(
+            "the rustflags (from {}) name no `-C linker=`",
+            config.rustflags_source
+        );
+    };
+    let help = |cmd: &mut Command| {
+        let output = cmd.output().ok().filter(|output| output.status.success())?;
+        Some(option_names(&String::from_utf8_lossy(&output.stdout)))
+    };
+    let Some(linker_options) = help(Command::new(linker).arg("--help")) else {
+        bail!("failed to run `{linker} --help`");
+    };
+    let llc =
+        toolchain_dir_of_linker(Path::new(linker)).map(|dir| dir.join("llvm-install/bin/llc"));
+    let llvm_options = llc
+        .as_ref()
+        .and_then(|llc| help(Command::new(llc).args(["-march=bpf", "--help-hidden"])));
+
+    let checks = config
+        .link_args()
+        .into_iter()
+        .map(|arg| {
+            let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+            let check = if !linker_options.contains(option_name(name)) {
+                LinkArgCheck::Unknown(format!("{linker} has no `{name}` option"))
+            } else if name != "--llvm-args" {
+                LinkArgCheck::Ok
+            } else if let Some(llvm_options) = &llvm_options {
+                match value
+                    .split_whitespace()
+                    .find(|flag| !llvm_options.contains(option_name(flag)))
+                {
+                    Some(flag) => LinkArgCheck::Unknown(format!("LLVM has no `{flag}` option")),
+                    None => LinkArgCheck::Ok,
+                }
+            } else {
+                LinkArgCheck::Unchecked(
+                    "no `llc` in the linker's toolchain to check LLVM options against".to_string(),
+                )
+            };
+            (arg.to_string(), check)
+        })
+        .collect();
+    Ok(checks)
+}
+
+/// The options listed in a `--help` text, without their leading dashes.
+fn option_names(help: &str) -> BTreeSet<String> {
+    help.split(|c: char| c.is_whitespace() || matches!(c, ',' | '[' | ']'))
+        .filter(|word| word.starts_with('-'))
+        .map(option_name)
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// `--name=value` or `-name <value>` as just `name`.
+fn option_name(flag: &str) -> &str {
+    let name = flag.trim_start_matches('-');
+    name.split(['=', '<']).next().unwrap_or_default()
+}
+
+/// `target.bpfel-unknown-none.rustflags` from a cargo config file.
+fn config_rustflags(config_path: &Path) -> Result<Vec<String>> {
+    let contents = fs::read_to_string(config_path)
+        .with_context(|| format!("failed to read {}", config_path.display()))?;
+    let config: toml::Table = contents
+        .parse()
+        .with_context(|| format!("failed to parse {}", config_path.display()))?;
+    let flags = config
+        .get("target")
+        .and_then(|target| target.get(TARGET))
+        .and_then(|target| target.get("rustflags"));
+    Ok(match flags {
+        Some(toml::Value::Array(flags)) => flags
+            .iter()
+            .filter_map(|flag| flag.as_str().map(String::from))
+            .collect(),
+        Some(toml::Value::String(flags)) => split_flags(flags),
+        _ => Vec::new(),
+    })
+}
+
+/// `(directory name, package name)` of each program in `programs/`, sorted
+/// by directory.
+fn programs(project_root: &Path) -> Result<Vec<(String, String)>> {
+    let Ok(entries) = fs::read_dir(project_root.join(PROGRAMS_DIR)) else {
+        return Ok(Vec::new());
+    };
+    let mut programs = Vec::new();
+    for entry in entries {
+        let dir = entry?.path();
+        let manifest_path = dir.join("Cargo.toml");
+        let Ok(contents) = fs::read_to_string(&manifest_path) else {
+            continue;
+        };
+        let manifest: toml::Table = contents
+            .parse()
+            .with_context(|| format!("failed to parse {}", manifest_path.display()))?;
+        let package = manifest
+            .get("package")
+            .and_then(|package| package.get("name"))
+            .and_then(toml::Value::as_str)
+            .with_context(|| format!("{} has no package name", manifest_path.display()))?;
+        let name = dir
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        programs.push((name, package.to_string()));
+    }
+    programs.sort();
+    Ok(programs)
+}
+
+/// File name cargo gives the `cdylib` of `package`.
+fn artifact_name(package: &str) -> String {
+    format!("lib{}.so", package.replace('-', "_"))
+}
+
+/// The directory under `target/<target>/` cargo writes `profile` to: its
+/// own name, except for the built-in profiles that keep their old names.
+fn profile_dir(profile: &str) -> &str {
+    match profile {
+        "dev" | "test" => "debug",
+        "bench" => "release",
+        profile => profile,
+    }
+}
+
+/// Check that `text` is a single JSON object, as a target spec must be,
+/// reporting the byte offset of the first error.
+fn check_json_object(text: &str) -> Result<(), String> {
+    let bytes = text.as_bytes();
+    let mut pos = skip_whitespace(bytes, 0);
+    if bytes.get(pos) != Some(&b'{') {
+        return Err("expected a JSON object".to_string());
+    }
+    pos = skip_json_value(bytes, pos)?;
+    pos = skip_whitespace(bytes, pos);
+    if pos != bytes.len() {
+        return Err(format!("unexpected trailing data at byte {pos}"));
+    }
+    Ok(())
+}
+
+fn skip_whitespace(bytes: &[u8], mut pos: usize) -> usize {
+    while bytes.get(pos).is_some_and(u8::is_ascii_whitespace) {
+        pos += 1;
+    }
+    pos
+}
+
+/// The offset just past the JSON value starting at `pos`.
+fn skip_json_value(bytes: &[u8], pos: usize) -> Result<usize, String> {
+    let err = |pos: usize| Err(format!("invalid JSON at byte {pos}"));
+    match bytes.get(pos) {
+        Some(&open @ (b'{' | b'[')) => {
+            let close = if open == b'{' { b'}' } else { b']' };
+            let mut pos = skip_whitespace(bytes, pos + 1);
+            if bytes.get(pos) == Some(&close) {
+                return Ok(pos + 1);
+            }
+            loop {
+                if open == b'{' {
+                    if bytes.get(pos) != Some(&b'"') {
+                        return err(pos);
+                    }
+                    pos = skip_whitespace(bytes, skip_json_value(bytes, pos)?);
+                    if bytes.get(pos) != Some(&b':') {
+                        return err(pos);
+                    }
+                    pos = skip_whitespace(bytes, pos + 1);
+                }
+                pos = skip_whitespace(bytes, skip_json_value(bytes, pos)?);
+                match bytes.get(pos) {
+                    Some(b',') => pos = skip_whitespace(bytes, pos + 1),
+                    Some(&c) if c == close => return Ok(pos + 1),
+                    _ => return err(pos),
+                }
+            }
+        }
+        Some(b'"') => {
+            let mut pos = pos + 1;
+            loop {
+                match bytes.get(pos) {
+                    Some(b'"') => return Ok(pos + 1),
+                    Some(b'\\') => pos += 2,
+                    Some(c) if *c >= 0x20 => pos += 1,
+                    _ => return err(pos),
+                }
+            }
+        }
+        Some(b'-' | b'0'..=b'9') => {
+            let end = pos
+                + bytes[pos..]
+                    .iter()
+                    .take_while(|c| matches!(c, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'))
+                    .count();
+            match std::str::from_utf8(&bytes[pos..end])
+                .ok()
+                .and_then(|n| n.parse::<f64>().ok())
+            {
+                Some(_) => Ok(end),
+                None => err(pos),
+            }
+        }
+        _ => {
+            for literal in ["true", "false", "null"] {
+                if bytes[pos..].starts_with(literal.as_bytes()) {
+                    return Ok(pos + literal.len());
+                }
+            }
+            err(pos)
+        }
+    }
+}
+
+fn split_flags(flags: &str) -> Vec<String> {
+    flags.split_whitespace().map(String::from).collect()
+}
+
+/// `s` as a quoted JSON string.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
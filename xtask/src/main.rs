@@ -1,22 +1,75 @@
 use anyhow::{bail, Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::collections::{BTreeMap, BTreeSet};
 use std::ffi::OsString;
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
 use walkdir::WalkDir;
 
+mod build;
+mod manifest;
+/// The test output prefixes, shared with the fixture's tests.
+#[path = "../../src/tests/report.rs"]
+mod report;
+mod settings;
+mod steps;
+
+use manifest::Manifest;
+use report::{CU_REPORT_PREFIX, SMOKE_PREFIX};
+use settings::{SettingPath, Settings};
+use steps::Steps;
+
 const LLVM_REPO: &str = "https://github.com/blueshift-gg/llvm-project.git";
 const LLVM_BRANCH: &str = "BPF_i128_ret";
 const LINKER_REPO: &str = "https://github.com/blueshift-gg/sbpf-linker";
 const LINKER_BRANCH: &str = "u128_mul_libcall";
 const GIT_DEPTH: &str = "1";
+const ARTIFACT_PATH: &str = "target/bpfel-unknown-none/release/libupstream_u128_test.so";
+const IR_DUMP_DIR: &str = "llvm_dump";
+/// Name of the program within a snapshot.
+const SNAPSHOT_ARTIFACT: &str = "program.so";
+/// File within a snapshot naming the toolchain directory its manifest is from.
+const SNAPSHOT_TOOLCHAIN: &str = "toolchain";
+/// Where the seeded sweep writes reproducer tests for failing vectors.
+const REPRO_FILE: &str = "target/u128-repro/seeded_vectors.rs";
+const DEFAULT_TOOLCHAIN: &str = "default";
+const DEFAULT_STACK_SIZE: u32 = 4096;
+const DEFAULT_CROSSCHECK_COUNT: u32 = 64;
+const DEFAULT_CU_THRESHOLD: f64 = 5.0;
+/// Stack sizes `stack-sweep` tries when none are given, in bytes.
+const DEFAULT_SWEEP_STACK_SIZES: &[u32] = &[512, 1024, 2048, 3072, 4096];
+/// Components and steps `build-profile` lists by default.
+const DEFAULT_PROFILE_TOP: usize = 10;
+/// Build log ninja keeps in the LLVM build tree.
+const NINJA_LOG: &str = ".ninja_log";
 
 /// xtask for setting up custom Rust compiler with i128 BPF support
 #[derive(Parser)]
 #[command(name = "xtask")]
 #[command(about = "Build automation for u128 BPF prototype")]
 struct Cli {
+    /// Named toolchain build in the cache to set up or use [default: default]
+    #[arg(long, global = true)]
+    toolchain: Option<String>,
+    /// Root of the toolchain cache, relative to the project root
+    /// [default: the platform cache dir]
+    #[arg(long, global = true, value_name = "PATH")]
+    cache_dir: Option<PathBuf>,
+    /// Delete a clone that failed or was left incomplete, so the next run
+    /// clones afresh instead of stopping at it
+    #[arg(long, global = true)]
+    clean_on_failure: bool,
+    /// Kill the LLVM or linker build if it runs longer than this many
+    /// minutes [default: no limit]. Clones and downloads aren't affected.
+    #[arg(long, global = true, value_name = "MINUTES")]
+    timeout_build: Option<u64>,
+    /// Also report each setup step starting, finishing or failing as a JSON
+    /// line on stderr, for tools that show setup progress
+    #[arg(long, global = true)]
+    progress_json: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -24,39 +77,405 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Set up the complete toolchain (LLVM + sbpf linker)
-    Setup,
+    Setup {
+        /// Skip the steps a previous, interrupted setup already completed
+        #[arg(long)]
+        resume: bool,
+        /// After a successful setup, delete the LLVM build tree (the installed
+        /// LLVM is kept). A later LLVM rebuild then starts from scratch.
+        #[arg(long)]
+        clean_intermediate: bool,
+        #[command(flatten)]
+        llvm: LlvmArgs,
+    },
     /// Clone and build the SBPF linker only
     BuildLinker,
     /// Clone and build LLVM with modified BPF backend
-    BuildLlvm,
+    BuildLlvm(LlvmArgs),
+    /// Summarize where the time went in the last LLVM build, from the
+    /// ninja log in its build tree
+    BuildProfile {
+        /// How many of the slowest components and steps to list
+        #[arg(long, default_value_t = DEFAULT_PROFILE_TOP)]
+        top: usize,
+    },
     /// Build the example project with the custom toolchain
-    Build,
+    Build(BuildArgs),
+    /// Build with two cached toolchains and compare the compute units each
+    /// op takes under mollusk
+    CuCompare {
+        /// Toolchain to compare against, as shown by `toolchains`
+        baseline: String,
+        /// Toolchain being evaluated
+        candidate: String,
+        /// Percent increase over the baseline that counts as a regression
+        #[arg(long, default_value_t = DEFAULT_CU_THRESHOLD)]
+        threshold: f64,
+    },
+    /// Run the built program over seeded operands and compare with the host
+    Crosscheck {
+        /// Seed for operand generation (random if omitted, printed either way)
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Number of operand pairs to generate
+        #[arg(long, default_value_t = DEFAULT_CROSSCHECK_COUNT)]
+        count: u32,
+        /// First build the program, then run every opcode once against that
+        /// build and print a pass/fail line per op
+        #[arg(long)]
+        all_ops: bool,
+    },
+    /// Rebuild at each of a series of BPF stack sizes, run every opcode once
+    /// against each build, and report the smallest size each op works at
+    StackSweep {
+        /// Stack sizes to try, in bytes, comma-separated
+        /// [default: 512,1024,2048,3072,4096]
+        #[arg(long, value_delimiter = ',')]
+        sizes: Vec<u32>,
+    },
+    /// Set up the toolchain, build the project and crosscheck it, skipping
+    /// setup steps that already completed
+    All {
+        #[command(flatten)]
+        llvm: LlvmArgs,
+    },
+    /// List the symbols the built program exports and check `entrypoint`
+    Symbols,
+    /// Build the program, run multiplies that need the 128-bit multiply
+    /// libcall, and fail unless the artifact links it
+    LibcallCheck,
+    /// Disassemble two builds of the program and report which functions
+    /// changed, with each one's change in instruction count
+    AsmDiff {
+        /// The `.so` to compare against
+        old: PathBuf,
+        /// The `.so` being evaluated
+        new: PathBuf,
+    },
+    /// Download a prebuilt toolchain into the cache instead of building one,
+    /// and point `.cargo/config.toml` at its linker
+    Fetch {
+        /// URL of a `.tar.gz` of a toolchain directory, with its SHA-256 at
+        /// the same URL plus `.sha256`
+        url: String,
+    },
+    /// Run the test suite against a prebuilt program instead of the local
+    /// build, such as the artifact from another CI job
+    TestArtifact {
+        /// Path to the `.so`, or a URL to download it from, with its SHA-256
+        /// at the same URL plus `.sha256`
+        source: String,
+    },
+    /// Save `.cargo/config.toml`, the built program, its IR dump and the
+    /// toolchain's manifest under a name in the cache, to restore later
+    Snapshot { name: String },
+    /// Put back the config, program and IR dump saved by `snapshot`
+    Restore { name: String },
+    /// List the toolchain builds present in the cache
+    Toolchains,
+    /// Point `.cargo/config.toml` at the linker of a cached toolchain build
+    Use {
+        /// Toolchain name, as shown by `toolchains`
+        name: String,
+    },
+    /// Check that the linker in `.cargo/config.toml` recognizes every
+    /// link arg the config passes it
+    ValidateConfig,
+    /// Regenerate `.cargo/config.toml` for the selected toolchain's linker
+    /// without cloning or building anything
+    WriteConfig(ConfigArgs),
+}
+
+/// Global options that change how setup runs its clone and build steps.
+#[derive(Clone, Copy)]
+struct SetupFlags {
+    clean_on_failure: bool,
+    /// Longest the LLVM or linker build may run before it is killed
+    build_timeout: Option<Duration>,
+    progress_json: bool,
+}
+
+/// Codegen flags written into `.cargo/config.toml`.
+#[derive(Args, Clone, Default)]
+struct ConfigArgs {
+    /// BPF stack size in bytes, passed to the linker's LLVM [default: 4096]
+    #[arg(long)]
+    stack_size: Option<u32>,
+    /// `-C target-cpu` for the BPF target (e.g. v3); LLVM's default if omitted
+    #[arg(long)]
+    target_cpu: Option<String>,
+}
+
+/// `ConfigArgs` after applying `xtask.toml` and the defaults.
+struct CodegenFlags {
+    stack_size: u32,
+    target_cpu: Option<String>,
+}
+
+/// How LLVM itself is compiled. Changing either option rebuilds LLVM.
+///
+/// A debug or assertion-enabled LLVM is slower to build and to run, but turns
+/// a silent miscompile into an assertion failure pointing at the bad lowering.
+#[derive(Args)]
+struct LlvmArgs {
+    /// Build LLVM without assertions, which are on by default: they slow the
+    /// build but turn a bad lowering into a loud failure
+    #[arg(long)]
+    no_llvm_assertions: bool,
+    /// Build LLVM in Debug rather than Release mode
+    #[arg(long)]
+    llvm_debug: bool,
+    /// Build from an existing llvm-project checkout instead of cloning
+    /// `LLVM_BRANCH`. It is rebuilt (incrementally) on every run.
+    #[arg(long, value_name = "PATH")]
+    llvm_path: Option<PathBuf>,
+}
+
+impl LlvmArgs {
+    fn build_type(&self) -> &'static str {
+        if self.llvm_debug {
+            "Debug"
+        } else {
+            "Release"
+        }
+    }
+
+    fn assertions(&self) -> &'static str {
+        if self.no_llvm_assertions {
+            "OFF"
+        } else {
+            "ON"
+        }
+    }
+}
+
+#[derive(Args, Default)]
+struct BuildArgs {
+    /// Write the built artifact to stdout instead of only leaving it in `target/`
+    /// (progress messages go to stderr)
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "so")]
+    stdout: Option<StdoutArtifact>,
+    /// Also write the resolved build configuration (rustflags, target,
+    /// profile, linker) to this file as JSON
+    #[arg(long, value_name = "PATH")]
+    record: Option<PathBuf>,
+    /// Build with verbose cargo/rustc output, `-Ztime-passes` and a
+    /// `--timings` report, to see where a slow or failing build spends its time
+    #[arg(long)]
+    verbose_rustc: bool,
+    /// Fail the build on any compiler warning (`-D warnings`)
+    #[arg(long)]
+    deny_warnings: bool,
+    /// Build for the custom target spec in this JSON file instead of
+    /// `bpfel-unknown-none`, e.g. to toggle target features that affect
+    /// 128-bit lowering
+    #[arg(long, value_name = "PATH")]
+    target_json: Option<PathBuf>,
+    /// Build with this cargo profile instead of `release`, e.g. one defined
+    /// in `Cargo.toml` with other opt-level or debug settings
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+    /// Build only this program from `programs/` (its directory name), instead
+    /// of the fixture and every program there
+    #[arg(long, value_name = "NAME")]
+    program: Option<String>,
+    /// Cargo features to enable, passed through to cargo; e.g. with
+    /// `--no-default-features`, `op-mul` builds the fixture with only its
+    /// multiply opcodes
+    #[arg(long, value_name = "FEATURES")]
+    features: Option<String>,
+    /// Don't enable the default features, which build every opcode
+    #[arg(long)]
+    no_default_features: bool,
+}
+
+/// Build output that `build --stdout` can emit
+#[derive(Clone, Copy, ValueEnum)]
+enum StdoutArtifact {
+    /// Raw bytes of the linked `.so`
+    So,
+    /// Textual LLVM IR dumped by the linker
+    Ir,
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
     let project_root = project_root()?;
 
+    let mut settings = Settings::load(&project_root)?;
+    let toolchain = settings
+        .resolve("toolchain", cli.toolchain)?
+        .unwrap_or_else(|| DEFAULT_TOOLCHAIN.to_string());
+    let cache = match settings.resolve("cache_dir", cli.cache_dir.map(SettingPath))? {
+        Some(SettingPath(path)) => project_root.join(path),
+        None => default_cache_dir()?,
+    };
+    let config_args = match &cli.command {
+        Commands::WriteConfig(args) => args.clone(),
+        _ => ConfigArgs::default(),
+    };
+    let codegen = CodegenFlags {
+        stack_size: settings
+            .resolve("stack_size", config_args.stack_size)?
+            .unwrap_or(DEFAULT_STACK_SIZE),
+        target_cpu: settings.resolve("target_cpu", config_args.target_cpu)?,
+    };
+    settings.print();
+
+    let base_dir = toolchain_dir(&cache, &toolchain);
+    let flags = SetupFlags {
+        clean_on_failure: cli.clean_on_failure,
+        build_timeout: cli
+            .timeout_build
+            .map(|minutes| Duration::from_secs(minutes * 60)),
+        progress_json: cli.progress_json,
+    };
+
     match cli.command {
-        Commands::Setup => {
-            setup_llvm()?;
-            setup_linker(&project_root)?;
+        Commands::Setup {
+            resume,
+            clean_intermediate,
+            llvm,
+        } => {
+            let mut steps = Steps::load(&base_dir, resume, flags.progress_json)?;
+            if !resume {
+                steps.reset()?;
+            }
+            setup_llvm(&base_dir, &llvm, flags, &mut steps)?;
+            setup_linker(&project_root, &base_dir, &codegen, flags, &mut steps)?;
             println!();
             println!("==========================================");
             println!("Setup complete!");
             println!();
+            steps.print_timings();
+            if clean_intermediate {
+                println!();
+                clean_llvm_build(&base_dir)?;
+            }
+            println!();
             println!("Build this project with:");
             println!("  cargo +nightly build-bpf");
             println!("==========================================");
         }
         Commands::BuildLinker => {
-            setup_linker(&project_root)?;
+            let mut steps = Steps::load(&base_dir, false, flags.progress_json)?;
+            setup_linker(&project_root, &base_dir, &codegen, flags, &mut steps)?;
+        }
+        Commands::BuildLlvm(llvm) => {
+            let mut steps = Steps::load(&base_dir, false, flags.progress_json)?;
+            setup_llvm(&base_dir, &llvm, flags, &mut steps)?;
+        }
+        Commands::BuildProfile { top } => {
+            build_profile(&base_dir, top)?;
+        }
+        Commands::Build(args) => {
+            let artifact = build::build_project(&project_root, &args)?;
+            match args.stdout {
+                Some(StdoutArtifact::So) => {
+                    let bytes = fs::read(&artifact)
+                        .with_context(|| format!("failed to read {}", artifact.display()))?;
+                    write_stdout(&bytes)?;
+                }
+                Some(StdoutArtifact::Ir) => {
+                    // Asked-for output that isn't there must fail the pipeline,
+                    // not hand it an empty stdout.
+                    let Some(ir_path) = find_ir_dump(&project_root) else {
+                        bail!("no LLVM IR dump to write to stdout");
+                    };
+                    let ir = fs::read(&ir_path)
+                        .with_context(|| format!("failed to read {}", ir_path.display()))?;
+                    write_stdout(&ir)?;
+                }
+                None => {
+                    if let Some(ir_path) = find_ir_dump(&project_root) {
+                        println!("  LLVM IR dump: {}", ir_path.display());
+                    }
+                }
+            }
+        }
+        Commands::CuCompare {
+            baseline,
+            candidate,
+            threshold,
+        } => {
+            cu_compare(
+                &project_root,
+                &cache,
+                [&baseline, &candidate],
+                threshold,
+                &codegen,
+            )?;
+        }
+        Commands::Crosscheck {
+            seed,
+            count,
+            all_ops,
+        } => {
+            if all_ops {
+                smoke_all_ops(&project_root)?;
+            }
+            crosscheck(&project_root, seed, count)?;
+        }
+        Commands::StackSweep { sizes } => {
+            stack_sweep(&project_root, &base_dir, &sizes, &codegen)?;
+        }
+        Commands::All { llvm } => {
+            quickstart(&project_root, &base_dir, &llvm, &codegen, flags)?;
+        }
+        Commands::Symbols => {
+            symbols(&project_root)?;
+        }
+        Commands::LibcallCheck => {
+            libcall_check(&project_root)?;
+        }
+        Commands::AsmDiff { old, new } => {
+            asm_diff(&base_dir, &old, &new)?;
+        }
+        Commands::Fetch { url } => {
+            fetch_toolchain(&project_root, &base_dir, &url, &codegen)?;
+        }
+        Commands::TestArtifact { source } => {
+            test_artifact(&project_root, &source)?;
+        }
+        Commands::ValidateConfig => {
+            validate_config(&project_root)?;
+        }
+        Commands::Snapshot { name } => {
+            snapshot(&project_root, &cache, &name)?;
         }
-        Commands::BuildLlvm => {
-            setup_llvm()?;
+        Commands::Restore { name } => {
+            restore(&project_root, &cache, &name)?;
         }
-        Commands::Build => {
-            build_project(&project_root)?;
+        Commands::Toolchains => {
+            list_toolchains(&project_root, &cache)?;
+        }
+        Commands::Use { name } => {
+            let dir = toolchain_dir(&cache, &name);
+            if let Some(manifest) = Manifest::load(&dir)? {
+                manifest.check_host(&dir)?;
+            }
+            let linker_bin = linker_bin(&dir);
+            if !linker_bin.exists() {
+                bail!(
+                    "toolchain '{name}' has no linker at {}; build it with \
+                     `cargo xtask --toolchain {name} setup`",
+                    linker_bin.display()
+                );
+            }
+            write_cargo_config(&project_root, &linker_bin, &codegen)?;
+            println!("Now using toolchain '{name}' ({})", linker_bin.display());
+        }
+        Commands::WriteConfig(_) => {
+            let linker_bin = linker_bin(&base_dir);
+            if !linker_bin.exists() {
+                bail!(
+                    "no linker at {}; build it with `cargo xtask --toolchain {} setup`",
+                    linker_bin.display(),
+                    toolchain
+                );
+            }
+            write_cargo_config(&project_root, &linker_bin, &codegen)?;
+            println!("Wrote .cargo/config.toml for {}", linker_bin.display());
         }
     }
 
@@ -76,44 +495,138 @@ fn project_root() -> Result<PathBuf> {
     }
 }
 
-fn cache_dir() -> PathBuf {
+/// The platform cache directory's `u128-bpf-toolchain`. Where there is
+/// none (e.g. no `HOME`), a cache directory must be given explicitly: a
+/// toolchain build takes tens of gigabytes, too much to put in a guessed
+/// location such as `/tmp`.
+fn default_cache_dir() -> Result<PathBuf> {
     // Build tools outside the project to avoid Cargo workspace issues
-    dirs::cache_dir()
-        .unwrap_or_else(|| PathBuf::from("/tmp"))
-        .join("u128-bpf-toolchain")
+    match dirs::cache_dir() {
+        Some(dir) => Ok(dir.join("u128-bpf-toolchain")),
+        None => bail!(
+            "can't determine this platform's cache directory (is HOME set?); the toolchain \
+             needs tens of gigabytes, so pass `--cache-dir PATH` or set `cache_dir` in {}",
+            settings::FILE_NAME
+        ),
+    }
+}
+
+/// Cache directory of the named toolchain build. The default toolchain lives
+/// at the cache root so caches from before named toolchains keep working.
+fn toolchain_dir(cache: &Path, name: &str) -> PathBuf {
+    if name == DEFAULT_TOOLCHAIN {
+        cache.to_path_buf()
+    } else {
+        cache.join("toolchains").join(name)
+    }
+}
+
+const LINKER_BIN: &str = "sbpf-linker/target/release/sbpf-linker";
+
+fn linker_bin(toolchain_dir: &Path) -> PathBuf {
+    toolchain_dir.join(LINKER_BIN)
 }
 
-fn setup_linker(project_root: &Path) -> Result<()> {
-    let base_dir = cache_dir();
+/// The cached toolchain a linker binary belongs to, if it is one of ours.
+fn toolchain_dir_of_linker(linker_bin: &Path) -> Option<PathBuf> {
+    if !linker_bin.ends_with(LINKER_BIN) {
+        return None;
+    }
+    linker_bin
+        .ancestors()
+        .nth(Path::new(LINKER_BIN).components().count())
+        .map(Path::to_path_buf)
+}
+
+fn setup_linker(
+    project_root: &Path,
+    base_dir: &Path,
+    codegen: &CodegenFlags,
+    flags: SetupFlags,
+    steps: &mut Steps,
+) -> Result<()> {
     let linker_dir = base_dir.join("sbpf-linker");
-    let linker_bin = linker_dir.join("target/release/sbpf-linker");
+    let linker_bin = linker_bin(base_dir);
 
     println!("  SBPF linker will be built in: {}", linker_dir.display());
 
     // Ensure cache directory exists
-    std::fs::create_dir_all(&base_dir)?;
+    std::fs::create_dir_all(base_dir)?;
 
     // 1. Clone SBPF linker if needed
     println!("[1/3] Cloning SBPF linker...");
-    if linker_dir.exists() {
-        println!("  sbpf-linker directory already exists, skipping clone");
-    } else {
-        run_command(
-            Command::new("git")
-                .args(["clone", "--depth", GIT_DEPTH, "--branch", LINKER_BRANCH, LINKER_REPO])
-                .arg(&linker_dir),
-            "clone sbpf-linker",
-        )?;
-    }
+    steps.run("linker-clone", || {
+        clone_repo(
+            LINKER_REPO,
+            LINKER_BRANCH,
+            &linker_dir,
+            "Cargo.toml",
+            flags.clean_on_failure,
+        )
+    })?;
 
     // 2. Build SBPF linker with LLVM_PREFIX pointing to our custom LLVM
     let llvm_install_dir = base_dir.join("llvm-install");
     println!("[2/3] Building SBPF linker (LLVM_PREFIX={})...", llvm_install_dir.display());
+    steps.run("linker-build", || {
+        build_linker(&linker_dir, &llvm_install_dir, flags.build_timeout)?;
+        Manifest::update(
+            base_dir,
+            &[
+                ("linker_branch", LINKER_BRANCH.to_string()),
+                ("linker_sha", manifest::git_head(&linker_dir)),
+                ("linker_built_at", manifest::now_unix()),
+                (
+                    "linker_llvm_version",
+                    manifest::llvm_version(&llvm_install_dir),
+                ),
+                ("host", manifest::host()),
+            ],
+        )
+    })?;
 
+    // 3. Update .cargo/config.toml with linker path
+    println!("[3/3] Updating .cargo/config.toml with linker path...");
+    steps.run("cargo-config", || {
+        write_cargo_config(project_root, &linker_bin, codegen)
+    })?;
+
+    println!("  SBPF linker ready at: {}", linker_bin.display());
+    Ok(())
+}
+
+fn build_linker(
+    linker_dir: &Path,
+    llvm_install_dir: &Path,
+    timeout: Option<Duration>,
+) -> Result<()> {
     let mut cmd = Command::new("cargo");
     cmd.args(["install", "--path", "."])
-        .env("LLVM_PREFIX", &llvm_install_dir)
-        .current_dir(&linker_dir);
+        .env("LLVM_PREFIX", llvm_install_dir)
+        .current_dir(linker_dir);
+
+    // `cargo install` refuses to replace an `sbpf-linker` installed from
+    // somewhere else, with an error that doesn't say where that is.
+    if let Some(source) = installed_linker_source() {
+        let source_dir = Path::new(&source);
+        if fs::canonicalize(source_dir).ok() == fs::canonicalize(linker_dir).ok() {
+            println!("  sbpf-linker already installed from this checkout, reinstalling");
+        } else if source_dir
+            .parent()
+            .is_some_and(|toolchain| matches!(Manifest::load(toolchain), Ok(Some(_))))
+        {
+            println!(
+                "  Replacing the sbpf-linker installed from another toolchain build ({source})"
+            );
+            cmd.arg("--force");
+        } else {
+            bail!(
+                "an sbpf-linker from {source} is already installed in cargo's bin directory.\n\
+                 Remove it with `cargo uninstall sbpf-linker` (or reinstall it from there \
+                 afterwards) and re-run `cargo xtask build-linker`."
+            );
+        }
+    }
 
     // On macOS, use Homebrew's llvm for libc++, zlib, and zstd
     // (macOS doesn't provide static libraries, and building them from source is complex)
@@ -166,77 +679,296 @@ fn setup_linker(project_root: &Path) -> Result<()> {
         cmd.env("LIBZSTD_PATH", format!("{}/lib", zstd_prefix));
     }
 
-    run_command(&mut cmd, "build sbpf-linker")?;
+    run_command_with_timeout(&mut cmd, "build sbpf-linker", timeout)
+}
 
-    // 3. Update .cargo/config.toml with linker path
-    println!("[3/3] Updating .cargo/config.toml with linker path...");
+fn write_cargo_config(project_root: &Path, linker_bin: &Path, flags: &CodegenFlags) -> Result<()> {
     let cargo_config_dir = project_root.join(".cargo");
     std::fs::create_dir_all(&cargo_config_dir)?;
 
+    let target_cpu = match &flags.target_cpu {
+        Some(cpu) => format!("\n    \"-C\", \"target-cpu={cpu}\","),
+        None => String::new(),
+    };
     let config_content = format!(
         r#"[target.bpfel-unknown-none]
 rustflags = [
     "-C", "linker={}",
     "-C", "panic=abort",
     "-C", "link-arg=--dump-module=llvm_dump",
-    "-C", "link-arg=--llvm-args=-bpf-stack-size=4096",
-    "-C", "relocation-model=static",
+    "-C", "link-arg=--llvm-args=-bpf-stack-size={}",
+    "-C", "relocation-model=static",{}
 ]
 
 [alias]
-build-bpf = "build --release --target bpfel-unknown-none -Zbuild-std=core,alloc"
+build-bpf = "build --release --target bpfel-unknown-none -Zbuild-std=core,alloc --workspace --exclude xtask"
 "#,
-        linker_bin.display()
+        linker_bin.display(),
+        flags.stack_size,
+        target_cpu
     );
 
     std::fs::write(cargo_config_dir.join("config.toml"), config_content)
         .context("failed to write .cargo/config.toml")?;
+    Ok(())
+}
 
-    println!("  SBPF linker ready at: {}", linker_bin.display());
+fn list_toolchains(project_root: &Path, cache: &Path) -> Result<()> {
+    let mut names = vec![DEFAULT_TOOLCHAIN.to_string()];
+    if let Ok(entries) = fs::read_dir(cache.join("toolchains")) {
+        let mut named: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        named.sort();
+        names.extend(named);
+    }
+    let config = fs::read_to_string(project_root.join(".cargo/config.toml")).unwrap_or_default();
+
+    for name in names {
+        let dir = toolchain_dir(cache, &name);
+        let linker = linker_bin(&dir);
+        let manifest = Manifest::load(&dir)?;
+        if manifest.is_none() && !linker.exists() {
+            continue;
+        }
+        let active = config.contains(&linker.display().to_string());
+        println!(
+            "{}{name} ({})",
+            if active { "* " } else { "  " },
+            dir.display()
+        );
+        let Some(manifest) = manifest else {
+            println!("    (no manifest)");
+            continue;
+        };
+        for component in ["llvm", "linker"] {
+            let sha = manifest.get(&format!("{component}_sha")).unwrap_or("-");
+            let built = manifest
+                .get(&format!("{component}_built_at"))
+                .map(manifest::format_unix)
+                .unwrap_or_else(|| "not built".to_string());
+            println!("    {component:<6} {:.12}  built {built}", sha);
+        }
+    }
     Ok(())
 }
 
-fn setup_llvm() -> Result<()> {
-    let base_dir = cache_dir();
+fn setup_llvm(
+    base_dir: &Path,
+    options: &LlvmArgs,
+    flags: SetupFlags,
+    steps: &mut Steps,
+) -> Result<()> {
     let llvm_src_dir = base_dir.join("llvm-project");
 
     println!("  LLVM will be built in: {}", base_dir.display());
 
     // Ensure cache directory exists
-    std::fs::create_dir_all(&base_dir)?;
+    std::fs::create_dir_all(base_dir)?;
 
     // 1. Clone LLVM repo if needed
-    println!("[1/2] Cloning LLVM...");
-    if llvm_src_dir.exists() {
-        println!("  llvm-project directory already exists, skipping clone");
-    } else {
-        run_command(
-            Command::new("git")
-                .args(["clone", "--depth", GIT_DEPTH, "--branch", LLVM_BRANCH, LLVM_REPO])
-                .arg(&llvm_src_dir),
-            "clone llvm-project",
-        )?;
-    }
+    let llvm_src_dir = match &options.llvm_path {
+        Some(path) => {
+            println!("[1/2] Using local LLVM checkout at {}", path.display());
+            if !path.join("llvm/CMakeLists.txt").exists() {
+                bail!(
+                    "{} does not look like an llvm-project checkout",
+                    path.display()
+                );
+            }
+            match manifest::git_branch(path) {
+                Some(branch) if branch == LLVM_BRANCH => {}
+                branch => eprintln!(
+                    "warning: {} is on branch {}, not {LLVM_BRANCH}; the 128-bit BPF changes \
+                     may be missing",
+                    path.display(),
+                    branch.as_deref().unwrap_or("(unknown)")
+                ),
+            }
+            path.clone()
+        }
+        None => {
+            println!("[1/2] Cloning LLVM...");
+            steps.run("llvm-clone", || {
+                let sentinel = "llvm/CMakeLists.txt";
+                clone_repo(
+                    LLVM_REPO,
+                    LLVM_BRANCH,
+                    &llvm_src_dir,
+                    sentinel,
+                    flags.clean_on_failure,
+                )
+            })?;
+            llvm_src_dir
+        }
+    };
 
     // 2. Build LLVM from source (skip if already built)
     let llvm_build_dir = base_dir.join("llvm-build");
     let llvm_install_dir = base_dir.join("llvm-install");
     let llvm_config = llvm_install_dir.join("bin/llvm-config");
 
-    if llvm_config.exists() {
-        println!("[2/2] LLVM already built (found {}), skipping", llvm_config.display());
-    } else {
-        println!("[2/2] Building LLVM (this may take a while)...");
+    steps.run("llvm-build", || {
+        // Builds from before these options were recorded used the defaults.
+        let manifest = Manifest::load(base_dir)?;
+        let built_with = |key, default| {
+            manifest
+                .as_ref()
+                .and_then(|m| m.get(key).map(String::from))
+                .unwrap_or_else(|| String::from(default))
+        };
+        let options_changed = built_with("llvm_build_type", "Release") != options.build_type()
+            || built_with("llvm_assertions", "ON") != options.assertions();
+
+        if llvm_config.exists() && !options_changed && options.llvm_path.is_none() {
+            println!(
+                "[2/2] LLVM already built (found {}), skipping",
+                llvm_config.display()
+            );
+            return Ok(());
+        }
+        if options.llvm_path.is_some() {
+            println!("[2/2] Building local LLVM checkout...");
+        } else if llvm_config.exists() {
+            println!(
+                "[2/2] LLVM build options changed (build type {}, assertions {}), rebuilding...",
+                options.build_type(),
+                options.assertions()
+            );
+        } else {
+            println!("[2/2] Building LLVM (this may take a while)...");
+        }
         std::fs::create_dir_all(&llvm_build_dir)?;
         std::fs::create_dir_all(&llvm_install_dir)?;
-        build_llvm(&llvm_src_dir, &llvm_build_dir, &llvm_install_dir)?;
-    }
+        build_llvm(
+            &llvm_src_dir,
+            &llvm_build_dir,
+            &llvm_install_dir,
+            options,
+            flags.build_timeout,
+        )?;
+        Manifest::update(
+            base_dir,
+            &[
+                (
+                    "llvm_branch",
+                    manifest::git_branch(&llvm_src_dir).unwrap_or_else(|| LLVM_BRANCH.to_string()),
+                ),
+                ("llvm_sha", manifest::git_head(&llvm_src_dir)),
+                ("llvm_source", llvm_src_dir.display().to_string()),
+                ("llvm_built_at", manifest::now_unix()),
+                ("llvm_build_type", options.build_type().to_string()),
+                ("llvm_assertions", options.assertions().to_string()),
+                ("llvm_version", manifest::llvm_version(&llvm_install_dir)),
+                ("host", manifest::host()),
+            ],
+        )
+    })?;
 
     println!("  LLVM installed to: {}", llvm_install_dir.display());
     Ok(())
 }
 
-fn build_llvm(src_dir: &Path, build_dir: &Path, install_prefix: &Path) -> Result<()> {
+/// Delete the LLVM build tree, which is only needed to rebuild LLVM, and
+/// report how much space that reclaimed.
+fn clean_llvm_build(base_dir: &Path) -> Result<()> {
+    let build_dir = base_dir.join("llvm-build");
+    if !build_dir.exists() {
+        println!("No LLVM build tree to clean at {}", build_dir.display());
+        return Ok(());
+    }
+    let size: u64 = WalkDir::new(&build_dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum();
+    fs::remove_dir_all(&build_dir)
+        .with_context(|| format!("failed to remove {}", build_dir.display()))?;
+    println!(
+        "Removed {} ({:.1} GiB reclaimed)",
+        build_dir.display(),
+        size as f64 / (1u64 << 30) as f64
+    );
+    Ok(())
+}
+
+/// Print the slowest components and steps of the last LLVM build, from the
+/// `NINJA_LOG` in its build tree: the time ninja recorded for each output,
+/// summed per source directory, so it shows what to cut (a target, the
+/// tools, assertions) to build faster.
+fn build_profile(base_dir: &Path, top: usize) -> Result<()> {
+    let log_path = base_dir.join("llvm-build").join(NINJA_LOG);
+    let contents = fs::read_to_string(&log_path).with_context(|| {
+        format!(
+            "failed to read {}; build LLVM with `cargo xtask build-llvm` first",
+            log_path.display()
+        )
+    })?;
+    // Lines are `start_ms end_ms mtime output hash`, tab-separated. Each
+    // rebuild appends, so a later entry for an output replaces earlier ones.
+    let mut outputs: BTreeMap<&str, Duration> = BTreeMap::new();
+    for line in contents.lines().filter(|line| !line.starts_with('#')) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [start, end, _, output, ..] = fields[..] else {
+            continue;
+        };
+        let (Ok(start), Ok(end)) = (start.parse::<u64>(), end.parse::<u64>()) else {
+            continue;
+        };
+        outputs.insert(output, Duration::from_millis(end.saturating_sub(start)));
+    }
+    if outputs.is_empty() {
+        bail!("{} records no build steps", log_path.display());
+    }
+
+    // Compiles are `<dir>/CMakeFiles/<target>.dir/...`; everything else is
+    // a link, archive or install step.
+    let mut components: BTreeMap<&str, Duration> = BTreeMap::new();
+    for (output, elapsed) in &outputs {
+        let component = output
+            .split_once("/CMakeFiles/")
+            .map_or("(link and other steps)", |(dir, _)| dir);
+        *components.entry(component).or_default() += *elapsed;
+    }
+    let total: Duration = outputs.values().sum();
+    let share =
+        |elapsed: Duration| elapsed.as_secs_f64() * 100.0 / total.as_secs_f64().max(f64::EPSILON);
+
+    println!(
+        "{} build steps, {} in all",
+        outputs.len(),
+        steps::format_duration(total)
+    );
+    println!("  (steps run in parallel, so this is more than the wall-clock time)");
+    let print_slowest = |title: &str, times: BTreeMap<&str, Duration>| {
+        let mut by_time: Vec<(&str, Duration)> = times.into_iter().collect();
+        by_time.sort_by_key(|&(_, elapsed)| std::cmp::Reverse(elapsed));
+        println!();
+        println!("{title}:");
+        for (name, elapsed) in by_time.into_iter().take(top) {
+            println!(
+                "  {:>10} {:>5.1}%  {name}",
+                steps::format_duration(elapsed),
+                share(elapsed)
+            );
+        }
+    };
+    print_slowest("Slowest components", components);
+    print_slowest("Slowest steps", outputs);
+    Ok(())
+}
+
+fn build_llvm(
+    src_dir: &Path,
+    build_dir: &Path,
+    install_prefix: &Path,
+    options: &LlvmArgs,
+    timeout: Option<Duration>,
+) -> Result<()> {
     let mut install_arg = OsString::from("-DCMAKE_INSTALL_PREFIX=");
     install_arg.push(install_prefix.as_os_str());
     let mut cmake_configure = Command::new("cmake");
@@ -248,16 +980,16 @@ fn build_llvm(src_dir: &Path, build_dir: &Path, install_prefix: &Path) -> Result
         .args([
             "-G",
             "Ninja",
-            "-DCMAKE_BUILD_TYPE=Release",
             "-DLLVM_BUILD_LLVM_DYLIB=ON",
-            "-DLLVM_ENABLE_ASSERTIONS=ON",
             "-DLLVM_ENABLE_PROJECTS=",
             "-DLLVM_ENABLE_RUNTIMES=",
             "-DLLVM_INSTALL_UTILS=ON",
             "-DLLVM_LINK_LLVM_DYLIB=ON",
             "-DLLVM_TARGETS_TO_BUILD=BPF",
         ])
-        .arg(install_arg);
+        .arg(install_arg)
+        .arg(format!("-DCMAKE_BUILD_TYPE={}", options.build_type()))
+        .arg(format!("-DLLVM_ENABLE_ASSERTIONS={}", options.assertions()));
 
     // On Linux, explicitly use clang to avoid C++ ABI mismatches with GCC
     if cfg!(target_os = "linux") {
@@ -287,12 +1019,7 @@ fn build_llvm(src_dir: &Path, build_dir: &Path, install_prefix: &Path) -> Result
         // symlinks so we can distinguish the two cases.
         .env("CMAKE_INSTALL_MODE", "ABS_SYMLINK");
     println!("Building LLVM with command {cmake_build:?}");
-    let status = cmake_build
-        .status()
-        .with_context(|| format!("failed to build LLVM with command {cmake_configure:?}"))?;
-    if !status.success() {
-        anyhow::bail!("failed to build LLVM with command {cmake_configure:?}: {status}");
-    }
+    run_command_with_timeout(cmake_build, "build LLVM", timeout)?;
 
     // Move targets over the symlinks that point to them.
     //
@@ -326,18 +1053,1057 @@ fn build_llvm(src_dir: &Path, build_dir: &Path, install_prefix: &Path) -> Result
     Ok(())
 }
 
-fn build_project(project_root: &Path) -> Result<()> {
-    println!("Building project with cargo +nightly...");
+/// Run the program's seeded-operand test, which feeds the same generated
+/// operands to mollusk and to the host reference implementation.
+fn crosscheck(project_root: &Path, seed: Option<u64>, count: u32) -> Result<()> {
+    let seed = seed.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default()
+    });
+    println!("Crosschecking {count} operand pairs with seed {seed}");
+    println!("  replay with: cargo xtask crosscheck --seed {seed} --count {count}");
+
     run_command(
         Command::new("cargo")
-            .args(["+nightly", "build-bpf"])
+            .args([
+                "test",
+                "--package",
+                "upstream-u128-test",
+                "seeded_operands_match_host",
+            ])
+            .args(["--", "--nocapture"])
+            .env("U128_SEED", seed.to_string())
+            .env("U128_VECTORS", count.to_string())
             .current_dir(project_root),
-        "build project",
+        "crosscheck",
+    )
+    .with_context(|| {
+        format!(
+            "crosscheck failed; replay with --seed {seed}, or run the single-vector \
+             reproducers in {}",
+            project_root.join(REPRO_FILE).display()
+        )
+    })?;
+    println!("Crosscheck passed (seed {seed})");
+    Ok(())
+}
+
+/// Build the program once and run the `every_op_smoke` test against it,
+/// which checks each opcode on one representative input.
+fn smoke_all_ops(project_root: &Path) -> Result<()> {
+    build::build_project(project_root, &BuildArgs::default())?;
+    println!("Running every opcode once");
+    run_command(
+        Command::new("cargo")
+            .args(["test", "--package", "upstream-u128-test", "every_op_smoke"])
+            .args(["--", "--nocapture"])
+            .current_dir(project_root),
+        "every-op smoke test",
+    )
+    .context("an opcode failed its smoke test; see the FAILED lines above")?;
+    println!("Every opcode passed");
+    Ok(())
+}
+
+/// Build the program with each of the `toolchains` in turn, run the
+/// `compute_units_per_op` test against each build and print the per-op
+/// difference. Fails if any op got more than `threshold` percent slower.
+/// `.cargo/config.toml` is restored afterwards, whatever happens.
+fn cu_compare(
+    project_root: &Path,
+    cache: &Path,
+    toolchains: [&str; 2],
+    threshold: f64,
+    codegen: &CodegenFlags,
+) -> Result<()> {
+    let config_path = project_root.join(".cargo/config.toml");
+    let saved_config = fs::read(&config_path).ok();
+
+    let measure = || -> Result<Vec<BTreeMap<String, u64>>> {
+        let mut reports = Vec::new();
+        for name in toolchains {
+            let dir = toolchain_dir(cache, name);
+            let linker_bin = linker_bin(&dir);
+            if !linker_bin.exists() {
+                bail!(
+                    "toolchain '{name}' has no linker at {}",
+                    linker_bin.display()
+                );
+            }
+            println!("==> {name}");
+            write_cargo_config(project_root, &linker_bin, codegen)?;
+            build::build_project(project_root, &BuildArgs::default())?;
+            reports.push(
+                compute_units_per_op(project_root)
+                    .with_context(|| format!("measuring toolchain '{name}' failed"))?,
+            );
+        }
+        Ok(reports)
+    };
+    let reports = measure();
+    restore_file(&config_path, saved_config)?;
+    let [baseline, candidate] = <[_; 2]>::try_from(reports?).expect("one report per toolchain");
+
+    println!();
+    println!(
+        "{:<16} {:>12} {:>12} {:>9}",
+        "op", toolchains[0], toolchains[1], "delta"
+    );
+    let mut regressions = Vec::new();
+    for (op, &before) in &baseline {
+        let Some(&after) = candidate.get(op) else {
+            println!("{op:<16} {before:>12} {:>12}", "-");
+            continue;
+        };
+        let delta = (after as f64 - before as f64) / before.max(1) as f64 * 100.0;
+        let flag = if delta > threshold {
+            "  REGRESSION"
+        } else {
+            ""
+        };
+        println!("{op:<16} {before:>12} {after:>12} {delta:>+8.1}%{flag}");
+        if delta > threshold {
+            regressions.push(op.as_str());
+        }
+    }
+    if !regressions.is_empty() {
+        bail!(
+            "{} regressed by more than {threshold}%: {}",
+            toolchains[1],
+            regressions.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Put back `path` as it was before: `saved` contents, or no file at all.
+fn restore_file(path: &Path, saved: Option<Vec<u8>>) -> Result<()> {
+    match saved {
+        Some(contents) => fs::write(path, contents)
+            .with_context(|| format!("failed to restore {}", path.display())),
+        None => {
+            fs::remove_file(path).ok();
+            Ok(())
+        }
+    }
+}
+
+/// Build the program with `-bpf-stack-size` set to each of `sizes` in turn,
+/// run the `every_op_smoke` test against each build, and print which ops
+/// pass at which size. A size the build itself fails at counts as a failure
+/// of every op. Fails if any op fails even at the largest size.
+/// `.cargo/config.toml` is restored afterwards, whatever happens.
+fn stack_sweep(
+    project_root: &Path,
+    base_dir: &Path,
+    sizes: &[u32],
+    codegen: &CodegenFlags,
+) -> Result<()> {
+    let linker_bin = linker_bin(base_dir);
+    if !linker_bin.exists() {
+        bail!(
+            "no linker at {}; build it with `cargo xtask setup`",
+            linker_bin.display()
+        );
+    }
+    let mut sizes = if sizes.is_empty() {
+        DEFAULT_SWEEP_STACK_SIZES.to_vec()
+    } else {
+        sizes.to_vec()
+    };
+    sizes.sort_unstable();
+    sizes.dedup();
+
+    let config_path = project_root.join(".cargo/config.toml");
+    let saved_config = fs::read(&config_path).ok();
+    let sweep = || -> Result<Vec<Option<BTreeMap<String, bool>>>> {
+        let mut results = Vec::new();
+        for &stack_size in &sizes {
+            println!("==> stack size {stack_size}");
+            let flags = CodegenFlags {
+                stack_size,
+                target_cpu: codegen.target_cpu.clone(),
+            };
+            write_cargo_config(project_root, &linker_bin, &flags)?;
+            match build::build_project(project_root, &BuildArgs::default()) {
+                Ok(_) => results.push(Some(smoke_results(project_root)?)),
+                Err(err) => {
+                    println!("build failed at stack size {stack_size}: {err:#}");
+                    results.push(None);
+                }
+            }
+        }
+        Ok(results)
+    };
+    let results = sweep();
+    restore_file(&config_path, saved_config)?;
+    let results = results?;
+
+    let ops: BTreeSet<&String> = results.iter().flatten().flat_map(BTreeMap::keys).collect();
+    if ops.is_empty() {
+        bail!("the build failed at every stack size");
+    }
+    let passed = |op: &String, index: usize| {
+        results[index].as_ref().and_then(|ops| ops.get(op)) == Some(&true)
+    };
+
+    println!();
+    print!("{:<20}", "op");
+    for size in &sizes {
+        print!(" {size:>6}");
+    }
+    println!(" {:>9}", "smallest");
+    let mut failing = Vec::new();
+    for op in ops {
+        print!("{op:<20}");
+        for (index, _) in sizes.iter().enumerate() {
+            let cell = match &results[index] {
+                None => "-",
+                Some(_) if passed(op, index) => "ok",
+                Some(_) => "FAIL",
+            };
+            print!(" {cell:>6}");
+        }
+        // The smallest size from which the op passes at every larger one.
+        let from = (0..sizes.len())
+            .rev()
+            .take_while(|&index| passed(op, index))
+            .last();
+        match from {
+            Some(index) => println!(" {:>9}", sizes[index]),
+            None => {
+                println!(" {:>9}", "none");
+                failing.push(op.as_str());
+            }
+        }
+    }
+    if !failing.is_empty() {
+        bail!(
+            "failed even at stack size {}: {}",
+            sizes[sizes.len() - 1],
+            failing.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Whether each op passed, from the `every_op_smoke` test run against the
+/// program as currently built. The test failing isn't an error here; only
+/// its not reporting any ops is.
+fn smoke_results(project_root: &Path) -> Result<BTreeMap<String, bool>> {
+    let output = Command::new("cargo")
+        .args(["test", "--package", "upstream-u128-test"])
+        .args(["--", "--nocapture", "--exact", "tests::every_op_smoke"])
+        .current_dir(project_root)
+        .stderr(Stdio::inherit())
+        .output()
+        .context("failed to run: every-op smoke test")?;
+    let results: BTreeMap<String, bool> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.strip_prefix(SMOKE_PREFIX)?.split_whitespace();
+            Some((fields.next()?.to_string(), fields.next()? == "ok"))
+        })
+        .collect();
+    if results.is_empty() {
+        bail!("the every-op smoke test printed no `{SMOKE_PREFIX}` lines");
+    }
+    Ok(results)
+}
+
+/// Total compute units per op from the `compute_units_per_op` test, run
+/// against the program as currently built.
+fn compute_units_per_op(project_root: &Path) -> Result<BTreeMap<String, u64>> {
+    let output = Command::new("cargo")
+        .args(["test", "--package", "upstream-u128-test"])
+        .args([
+            "--",
+            "--nocapture",
+            "--exact",
+            "tests::compute_units_per_op",
+        ])
+        .current_dir(project_root)
+        .stderr(Stdio::inherit())
+        .output()
+        .context("failed to run: compute units test")?;
+    if !output.status.success() {
+        bail!("command failed: compute units test");
+    }
+    let report: BTreeMap<String, u64> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.strip_prefix(CU_REPORT_PREFIX)?.split_whitespace();
+            Some((fields.next()?.to_string(), fields.next()?.parse().ok()?))
+        })
+        .collect();
+    if report.is_empty() {
+        bail!("the compute units test printed no `{CU_REPORT_PREFIX}` lines");
+    }
+    Ok(report)
+}
+
+/// Where the `sbpf-linker` in cargo's bin directory was installed from, per
+/// `cargo install --list` (a path, or a git URL for git installs).
+fn installed_linker_source() -> Option<String> {
+    let output = Command::new("cargo")
+        .args(["install", "--list"])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find(|line| line.starts_with("sbpf-linker "))
+        .and_then(|line| Some(line.split_once(" (")?.1.strip_suffix("):")?.to_string()))
+}
+
+/// Setup, build and crosscheck in one go, stopping at the first phase that
+/// fails and summarizing how long each phase took either way.
+fn quickstart(
+    project_root: &Path,
+    base_dir: &Path,
+    llvm: &LlvmArgs,
+    codegen: &CodegenFlags,
+    flags: SetupFlags,
+) -> Result<()> {
+    let mut phases: Vec<(&str, Duration, bool)> = Vec::new();
+    let mut phase = |name, f: &mut dyn FnMut() -> Result<()>| {
+        println!("==> {name}");
+        let start = Instant::now();
+        let result = f();
+        phases.push((name, start.elapsed(), result.is_ok()));
+        result.with_context(|| format!("{name} failed"))
+    };
+
+    let result = phase("setup", &mut || {
+        let mut steps = Steps::load(base_dir, true, flags.progress_json)?;
+        setup_llvm(base_dir, llvm, flags, &mut steps)?;
+        setup_linker(project_root, base_dir, codegen, flags, &mut steps)
+    })
+    .and_then(|()| {
+        phase("build", &mut || {
+            build::build_project(project_root, &BuildArgs::default()).map(drop)
+        })
+    })
+    .and_then(|()| {
+        phase("crosscheck", &mut || {
+            crosscheck(project_root, None, DEFAULT_CROSSCHECK_COUNT)
+        })
+    });
+
+    println!();
+    println!("==========================================");
+    for (name, elapsed, ok) in &phases {
+        let status = if *ok { "ok" } else { "FAILED" };
+        println!("  {name:<12} {:>8.1}s  {status}", elapsed.as_secs_f64());
+    }
+    match &result {
+        Ok(()) => println!("All phases passed."),
+        Err(_) => println!("Stopped at the first failure; fix it and re-run `cargo xtask all`."),
+    }
+    println!("==========================================");
+    result
+}
+
+/// Defined symbols of `elf` as `(type, name)`, from the symbol table or,
+/// with `dynamic`, the dynamic symbol table. Uses `$LLVM_NM`, else
+/// `llvm-nm`, else `nm`.
+fn nm_symbols(elf: &Path, dynamic: bool) -> Result<Vec<(char, String)>> {
+    let candidates = match std::env::var("LLVM_NM") {
+        Ok(nm) => vec![nm],
+        Err(_) => vec!["llvm-nm".to_string(), "nm".to_string()],
+    };
+    for nm in &candidates {
+        let mut cmd = Command::new(nm);
+        cmd.arg("--defined-only");
+        if dynamic {
+            cmd.arg("--dynamic");
+        }
+        let Ok(output) = cmd.arg(elf).output() else {
+            continue;
+        };
+        if !output.status.success() {
+            bail!(
+                "{nm} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        return Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace().rev();
+                let name = fields.next()?;
+                let kind = fields.next()?.chars().next()?;
+                Some((kind, name.to_string()))
+            })
+            .collect());
+    }
+    bail!(
+        "none of {} could be run; set LLVM_NM",
+        candidates.join(", ")
+    )
+}
+
+/// Disassembly of `elf`, as the instructions of each function with their
+/// addresses stripped so code that only moved compares equal. Uses
+/// `$LLVM_OBJDUMP`, else the toolchain's `llvm-objdump`, else the one on
+/// `PATH`.
+fn disassemble(base_dir: &Path, elf: &Path) -> Result<BTreeMap<String, Vec<String>>> {
+    let candidates = match std::env::var("LLVM_OBJDUMP") {
+        Ok(objdump) => vec![PathBuf::from(objdump)],
+        Err(_) => vec![
+            base_dir.join("llvm-install/bin/llvm-objdump"),
+            PathBuf::from("llvm-objdump"),
+        ],
+    };
+    for objdump in &candidates {
+        let Ok(output) = Command::new(objdump)
+            .args(["-d", "--no-show-raw-insn"])
+            .arg(elf)
+            .output()
+        else {
+            continue;
+        };
+        if !output.status.success() {
+            bail!(
+                "{} failed on {}: {}",
+                objdump.display(),
+                elf.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let mut functions: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut current = None;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for line in stdout.lines() {
+            // A function starts at a line like `0000000000000120 <entrypoint>:`.
+            if let Some(name) = line
+                .strip_suffix(">:")
+                .and_then(|line| Some(line.split_once(" <")?.1))
+            {
+                current = Some(name.to_string());
+                functions.entry(name.to_string()).or_default();
+                continue;
+            }
+            let (Some(name), Some((address, instruction))) = (&current, line.split_once(':'))
+            else {
+                continue;
+            };
+            let (address, instruction) = (address.trim(), instruction.trim());
+            let is_address = !address.is_empty() && address.chars().all(|c| c.is_ascii_hexdigit());
+            if is_address && !instruction.is_empty() {
+                functions
+                    .entry(name.clone())
+                    .or_default()
+                    .push(instruction.to_string());
+            }
+        }
+        return Ok(functions);
+    }
+    let tried: Vec<String> = candidates
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    bail!(
+        "none of {} could be run; set LLVM_OBJDUMP",
+        tried.join(", ")
+    )
+}
+
+/// Print each function that differs between the disassembly of `old` and
+/// `new`, and how its instruction count changed.
+fn asm_diff(base_dir: &Path, old: &Path, new: &Path) -> Result<()> {
+    for elf in [old, new] {
+        if !elf.exists() {
+            bail!("{} not found", elf.display());
+        }
+    }
+    let old_functions = disassemble(base_dir, old)?;
+    let new_functions = disassemble(base_dir, new)?;
+    let names: BTreeSet<&String> = old_functions.keys().chain(new_functions.keys()).collect();
+
+    println!(
+        "Functions changed from {} to {}:",
+        old.display(),
+        new.display()
+    );
+    let mut changed = 0;
+    for name in &names {
+        let line = match (old_functions.get(*name), new_functions.get(*name)) {
+            (Some(before), Some(after)) if before == after => continue,
+            (Some(before), Some(after)) => {
+                let delta = after.len() as i64 - before.len() as i64;
+                format!(
+                    "changed  {name}: {} -> {} instructions ({delta:+})",
+                    before.len(),
+                    after.len()
+                )
+            }
+            (Some(before), None) => format!("removed  {name}: {} instructions", before.len()),
+            (None, Some(after)) => format!("added    {name}: {} instructions", after.len()),
+            (None, None) => unreachable!(),
+        };
+        println!("  {line}");
+        changed += 1;
+    }
+    let total =
+        |functions: &BTreeMap<String, Vec<String>>| functions.values().map(Vec::len).sum::<usize>();
+    println!(
+        "{changed} of {} functions differ; {} -> {} instructions in all",
+        names.len(),
+        total(&old_functions),
+        total(&new_functions)
+    );
+    Ok(())
+}
+
+/// Install the toolchain archive at `url` into `base_dir`. The archive holds
+/// a toolchain directory's contents (its manifest, `llvm-install/` and the
+/// linker binary at `LINKER_BIN`), and is only unpacked once it matches the
+/// SHA-256 published next to it.
+fn fetch_toolchain(
+    project_root: &Path,
+    base_dir: &Path,
+    url: &str,
+    codegen: &CodegenFlags,
+) -> Result<()> {
+    let linker_bin = linker_bin(base_dir);
+    if linker_bin.exists() {
+        bail!(
+            "a toolchain is already installed at {}; fetch into another one with \
+             `--toolchain NAME`",
+            base_dir.display()
+        );
+    }
+    let downloads = base_dir.join("downloads");
+    let archive = downloads.join("toolchain.tar.gz");
+    download_verified(url, &archive)?;
+
+    run_command(
+        Command::new("tar")
+            .arg("-xzf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(base_dir),
+        "unpack toolchain",
+    )?;
+    fs::remove_dir_all(&downloads).ok();
+
+    let Some(manifest) = Manifest::load(base_dir)? else {
+        bail!("the archive from {url} has no toolchain manifest; is it a toolchain directory?");
+    };
+    manifest.check_host(base_dir)?;
+    if !linker_bin.exists() {
+        bail!("the archive from {url} has no linker at {LINKER_BIN}");
+    }
+    write_cargo_config(project_root, &linker_bin, codegen)?;
+    println!(
+        "Installed toolchain at {} and wrote .cargo/config.toml",
+        base_dir.display()
+    );
+    Ok(())
+}
+
+/// Directory of snapshot `name` in the cache.
+fn snapshot_dir(cache: &Path, name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        bail!("invalid snapshot name '{name}'");
+    }
+    Ok(cache.join("snapshots").join(name))
+}
+
+/// Save the project's build state as snapshot `name`, replacing any snapshot
+/// of that name.
+fn snapshot(project_root: &Path, cache: &Path, name: &str) -> Result<()> {
+    let dir = snapshot_dir(cache, name)?;
+    let artifact = project_root.join(ARTIFACT_PATH);
+    if !artifact.exists() {
+        bail!("no built program at {}; build it first", artifact.display());
+    }
+    if dir.exists() {
+        println!("Replacing snapshot '{name}'");
+        fs::remove_dir_all(&dir).with_context(|| format!("failed to remove {}", dir.display()))?;
+    }
+    fs::create_dir_all(&dir)?;
+
+    let copy = |from: &Path, to: &str| {
+        fs::copy(from, dir.join(to))
+            .with_context(|| format!("failed to copy {} into the snapshot", from.display()))
+    };
+    copy(&project_root.join(".cargo/config.toml"), "config.toml")?;
+    copy(&artifact, SNAPSHOT_ARTIFACT)?;
+    let dump_dir = project_root.join(IR_DUMP_DIR);
+    if dump_dir.exists() {
+        copy_dir(&dump_dir, &dir.join(IR_DUMP_DIR))?;
+    }
+    let config = build::BuildConfig::resolve(project_root)?;
+    if let Some(toolchain) = config
+        .linker()
+        .and_then(|linker| toolchain_dir_of_linker(Path::new(linker)))
+    {
+        let manifest = toolchain.join(manifest::MANIFEST_FILE);
+        if manifest.exists() {
+            copy(&manifest, manifest::MANIFEST_FILE)?;
+            fs::write(
+                dir.join(SNAPSHOT_TOOLCHAIN),
+                toolchain.display().to_string(),
+            )?;
+        }
+    }
+    println!("Saved snapshot '{name}' to {}", dir.display());
+    Ok(())
+}
+
+/// Restore the config, program and IR dump of snapshot `name`, warning if
+/// the toolchain it was built with has been rebuilt since.
+fn restore(project_root: &Path, cache: &Path, name: &str) -> Result<()> {
+    let dir = snapshot_dir(cache, name)?;
+    if !dir.exists() {
+        let snapshots: Vec<String> = fs::read_dir(cache.join("snapshots"))
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        bail!(
+            "no snapshot '{name}' (found: {})",
+            if snapshots.is_empty() {
+                "none".to_string()
+            } else {
+                snapshots.join(", ")
+            }
+        );
+    }
+
+    let copy = |from: &str, to: &Path| {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(dir.join(from), to)
+            .with_context(|| format!("failed to restore {}", to.display()))
+            .map(drop)
+    };
+    copy("config.toml", &project_root.join(".cargo/config.toml"))?;
+    copy(SNAPSHOT_ARTIFACT, &project_root.join(ARTIFACT_PATH))?;
+    let dump_dir = project_root.join(IR_DUMP_DIR);
+    if dump_dir.exists() {
+        fs::remove_dir_all(&dump_dir)
+            .with_context(|| format!("failed to remove {}", dump_dir.display()))?;
+    }
+    if dir.join(IR_DUMP_DIR).exists() {
+        copy_dir(&dir.join(IR_DUMP_DIR), &dump_dir)?;
+    }
+
+    if let Ok(toolchain) = fs::read_to_string(dir.join(SNAPSHOT_TOOLCHAIN)) {
+        let current = fs::read_to_string(Path::new(&toolchain).join(manifest::MANIFEST_FILE)).ok();
+        let saved = fs::read_to_string(dir.join(manifest::MANIFEST_FILE)).ok();
+        if current != saved {
+            eprintln!(
+                "warning: the toolchain at {toolchain} has changed since snapshot '{name}'; the \
+                 restored config now points at a different linker build than the restored program's"
+            );
+        }
+    }
+    println!("Restored snapshot '{name}'");
+    Ok(())
+}
+
+/// Copy the directory tree at `from` to `to`.
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    for entry in WalkDir::new(from) {
+        let entry = entry.with_context(|| format!("failed to read {}", from.display()))?;
+        let target = to.join(entry.path().strip_prefix(from)?);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else {
+            fs::copy(entry.path(), &target)
+                .with_context(|| format!("failed to copy {}", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Report how the linker takes each link arg in the build's rustflags,
+/// failing if it doesn't recognize one.
+fn validate_config(project_root: &Path) -> Result<()> {
+    let config = build::BuildConfig::resolve(project_root)?;
+    let checks = build::check_link_args(&config)?;
+    println!(
+        "Link args (from {}) against {}:",
+        config.rustflags_source,
+        config.linker().unwrap_or_default()
+    );
+    let mut unknown = 0;
+    for (arg, check) in &checks {
+        match check {
+            build::LinkArgCheck::Ok => println!("  ok         {arg}"),
+            build::LinkArgCheck::Unchecked(reason) => println!("  unchecked  {arg} ({reason})"),
+            build::LinkArgCheck::Unknown(problem) => {
+                unknown += 1;
+                println!("  unknown    {arg} ({problem})");
+            }
+        }
+    }
+    if unknown > 0 {
+        bail!(
+            "{unknown} link arg(s) not recognized; rebuild the linker with \
+             `cargo xtask build-linker` or regenerate the config with `cargo xtask write-config`"
+        );
+    }
+    Ok(())
+}
+
+/// Run the tests with `U128_FIXTURE_SO` pointing at `source`, downloading it
+/// into `target/u128-artifact/` first if it is a URL.
+fn test_artifact(project_root: &Path, source: &str) -> Result<()> {
+    let artifact = if source.starts_with("https://") || source.starts_with("http://") {
+        let name = source
+            .rsplit('/')
+            .next()
+            .filter(|name| name.ends_with(".so"))
+            .unwrap_or("artifact.so");
+        let dest = project_root.join("target/u128-artifact").join(name);
+        download_verified(source, &dest)?;
+        dest
+    } else {
+        let path = PathBuf::from(source);
+        if !path.is_file() {
+            bail!("no program at {source}");
+        }
+        if path.extension().is_none_or(|ext| ext != "so") {
+            bail!("{source} is not a `.so`; mollusk only loads programs by that name");
+        }
+        fs::canonicalize(&path).with_context(|| format!("failed to resolve {source}"))?
+    };
+
+    println!("Testing against {}...", artifact.display());
+    run_command(
+        Command::new("cargo")
+            .args(["test", "--package", "upstream-u128-test"])
+            .env("U128_FIXTURE_SO", &artifact)
+            .current_dir(project_root),
+        "test artifact",
+    )
+}
+
+/// Download `url` to `dest`, keeping it only if it matches the SHA-256
+/// published at `url` plus `.sha256` (as written by `sha256sum`).
+fn download_verified(url: &str, dest: &Path) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut checksum_file = dest.as_os_str().to_owned();
+    checksum_file.push(".sha256");
+    let checksum_file = PathBuf::from(checksum_file);
+
+    println!("Downloading {url}...");
+    run_command(
+        Command::new("curl")
+            .args(["--fail", "--location", "--output"])
+            .arg(dest)
+            .arg(url),
+        "download",
+    )?;
+    run_command(
+        Command::new("curl")
+            .args([
+                "--fail",
+                "--location",
+                "--silent",
+                "--show-error",
+                "--output",
+            ])
+            .arg(&checksum_file)
+            .arg(format!("{url}.sha256")),
+        "download checksum",
     )?;
-    println!("Build complete!");
+
+    // `sha256sum` output: the digest, then the file name.
+    let expected = fs::read_to_string(&checksum_file)
+        .with_context(|| format!("failed to read {}", checksum_file.display()))?;
+    let expected = expected
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if expected.len() != 64 || !expected.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("{url}.sha256 does not contain a SHA-256 digest");
+    }
+    let actual = sha256_of(dest)?;
+    if actual != expected {
+        fs::remove_file(dest).ok();
+        bail!("checksum mismatch for {url}: expected {expected}, got {actual}; refusing to use it");
+    }
+    println!("  checksum OK ({actual})");
     Ok(())
 }
 
+/// Lowercase hex SHA-256 of the file at `path`, from `sha256sum` or, where
+/// that's missing (macOS), `shasum -a 256`.
+fn sha256_of(path: &Path) -> Result<String> {
+    let output = Command::new("sha256sum")
+        .arg(path)
+        .output()
+        .or_else(|_| {
+            Command::new("shasum")
+                .args(["-a", "256"])
+                .arg(path)
+                .output()
+        })
+        .context("failed to run sha256sum or shasum")?;
+    if !output.status.success() {
+        bail!("failed to checksum {}", path.display());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase())
+}
+
+/// Print the built program's exported symbols and fail unless `entrypoint`
+/// is among them, so a linkage regression isn't mistaken for a codegen one.
+fn symbols(project_root: &Path) -> Result<()> {
+    let artifact = project_root.join(ARTIFACT_PATH);
+    if !artifact.exists() {
+        bail!(
+            "{} not found; run `cargo xtask build` first",
+            artifact.display()
+        );
+    }
+
+    let symbols = nm_symbols(&artifact, false)?;
+    let dynamic = nm_symbols(&artifact, true).unwrap_or_default();
+    println!("Exported symbols of {}:", artifact.display());
+    for (kind, name) in symbols.iter().filter(|(kind, _)| kind.is_ascii_uppercase()) {
+        let dynsym = dynamic.iter().any(|(_, dyn_name)| dyn_name == name);
+        println!("  {kind} {name}{}", if dynsym { "  (dynamic)" } else { "" });
+    }
+
+    match symbols.iter().find(|(_, name)| name == "entrypoint") {
+        // The symbol table can't show the signature; name what the loader
+        // calls so a mismatch in src/lib.rs is easy to spot.
+        Some(('T', _)) => println!(
+            "entrypoint: present, global text symbol \
+             (expected `#[no_mangle] unsafe fn entrypoint(*mut u8) -> u64`)"
+        ),
+        Some((kind, _)) => bail!(
+            "entrypoint has symbol type '{kind}', expected a global text symbol ('T'); \
+             check it is still `pub` and `#[unsafe(no_mangle)]`"
+        ),
+        None => bail!(
+            "entrypoint is missing from {}; the loader won't find the program's entry",
+            artifact.display()
+        ),
+    }
+    if symbols.iter().any(|(_, name)| name == build::MUL_LIBCALL) {
+        println!(
+            "{}: present, so 128-bit multiplies go through it",
+            build::MUL_LIBCALL
+        );
+    } else {
+        println!(
+            "{}: missing; every 128-bit multiply was inlined or lowered some other way",
+            build::MUL_LIBCALL
+        );
+    }
+    Ok(())
+}
+
+/// Build the program, check its symbol table for `MUL_LIBCALL` and run the
+/// `libcall_operands_match_host` test against it, whose multiplies can't be
+/// narrowed below 128 bits. Between them they show the libcall was linked
+/// and that the multiplies going through it are right.
+fn libcall_check(project_root: &Path) -> Result<()> {
+    let artifact = build::build_project(project_root, &BuildArgs::default())?;
+    let symbols = nm_symbols(&artifact, false)?;
+    if !symbols.iter().any(|(_, name)| name == build::MUL_LIBCALL) {
+        bail!(
+            "{} doesn't contain `{}`: the 128-bit multiplies were inlined or lowered \
+             some other way",
+            artifact.display(),
+            build::MUL_LIBCALL
+        );
+    }
+    println!("{}: present in {}", build::MUL_LIBCALL, artifact.display());
+
+    println!("Running multiplies that need {}", build::MUL_LIBCALL);
+    run_command(
+        Command::new("cargo")
+            .args([
+                "test",
+                "--package",
+                "upstream-u128-test",
+                "libcall_operands_match_host",
+            ])
+            .current_dir(project_root),
+        "libcall operand test",
+    )
+    .with_context(|| {
+        format!(
+            "a multiply through {} disagreed with the host",
+            build::MUL_LIBCALL
+        )
+    })?;
+    println!(
+        "{} is linked and its multiplies match the host",
+        build::MUL_LIBCALL
+    );
+    Ok(())
+}
+
+/// Locate the textual IR written by the linker's `--dump-module=llvm_dump`,
+/// preferring the post-optimization module when several are present.
+///
+/// The dump is a debugging aid some linker versions don't produce, so a
+/// missing dump is only warned about here; it fails the build only when
+/// `--stdout ir` asked for it.
+fn find_ir_dump(project_root: &Path) -> Option<PathBuf> {
+    let dump_dir = project_root.join(IR_DUMP_DIR);
+    let mut dumps: Vec<PathBuf> = WalkDir::new(&dump_dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path().to_path_buf())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ll"))
+        .collect();
+    dumps.sort();
+
+    let post_opt = dumps.iter().find(|path| {
+        path.file_name()
+            .is_some_and(|name| name.to_string_lossy().contains("post-opt"))
+    });
+    let found = post_opt.or(dumps.last()).cloned();
+    if found.is_none() {
+        eprintln!(
+            "warning: no LLVM IR dump (*.ll) found in {}; the linker may not support --dump-module",
+            dump_dir.display()
+        );
+    }
+    found
+}
+
+fn write_stdout(bytes: &[u8]) -> Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    stdout
+        .write_all(bytes)
+        .context("failed to write to stdout")?;
+    stdout.flush().context("failed to flush stdout")?;
+    Ok(())
+}
+
+/// Whether `dir` holds a finished clone: git resolves its `HEAD` and the
+/// checkout got as far as `sentinel`. An interrupted clone can leave either
+/// missing while the directory itself exists.
+fn is_complete_clone(dir: &Path, sentinel: &str) -> bool {
+    let head = Command::new("git")
+        .args(["rev-parse", "--verify", "--quiet", "HEAD"])
+        .current_dir(dir)
+        .output();
+    head.is_ok_and(|output| output.status.success()) && dir.join(sentinel).exists()
+}
+
+/// Shallow-clone `branch` of `repo` into `dir`, unless a complete clone is
+/// already there. A partial one is deleted with `clean_on_failure`, and
+/// otherwise reported rather than mistaken for a finished clone; with
+/// `clean_on_failure` a clone that fails is deleted too.
+fn clone_repo(
+    repo: &str,
+    branch: &str,
+    dir: &Path,
+    sentinel: &str,
+    clean_on_failure: bool,
+) -> Result<()> {
+    let name = dir.file_name().unwrap_or_default().to_string_lossy();
+    if dir.exists() {
+        if is_complete_clone(dir, sentinel) {
+            println!("  {name} directory already exists, skipping clone");
+            return Ok(());
+        }
+        if !clean_on_failure {
+            bail!(
+                "{} is an incomplete clone, probably from an interrupted run; delete it or \
+                 re-run with --clean-on-failure",
+                dir.display()
+            );
+        }
+        println!("  {name} is an incomplete clone, removing it (--clean-on-failure)");
+        fs::remove_dir_all(dir).with_context(|| format!("failed to remove {}", dir.display()))?;
+    }
+    let result = run_command(
+        Command::new("git")
+            .args(["clone", "--depth", GIT_DEPTH, "--branch", branch, repo])
+            .arg(dir),
+        &format!("clone {name}"),
+    );
+    if result.is_err() && clean_on_failure && dir.exists() {
+        println!(
+            "  removing the partial clone at {} (--clean-on-failure)",
+            dir.display()
+        );
+        fs::remove_dir_all(dir).with_context(|| format!("failed to remove {}", dir.display()))?;
+    }
+    result
+}
+
+/// `run_command`, killing the command and everything it started if it is
+/// still running after `timeout`.
+fn run_command_with_timeout(
+    cmd: &mut Command,
+    description: &str,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let Some(timeout) = timeout else {
+        return run_command(cmd, description);
+    };
+    // In its own process group, so the ninja and compiler processes a hung
+    // build leaves behind can be killed along with it.
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(cmd, 0);
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("failed to run: {description}"))?;
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child
+            .try_wait()
+            .with_context(|| format!("failed to run: {description}"))?
+        {
+            if !status.success() {
+                bail!("command failed: {description}");
+            }
+            return Ok(());
+        }
+        if start.elapsed() >= timeout {
+            break;
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+
+    eprintln!(
+        "{description} still running after {} minutes, killing it",
+        timeout.as_secs() / 60
+    );
+    #[cfg(unix)]
+    {
+        let group = format!("-{}", child.id());
+        let _ = Command::new("kill").args(["-TERM", "--", &group]).status();
+        // Give it a moment to exit on its own before forcing it.
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while child.try_wait().ok().flatten().is_none() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(200));
+        }
+        let _ = Command::new("kill").args(["-KILL", "--", &group]).status();
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+    bail!(
+        "{description} timed out after {} minutes (--timeout-build); \
+         the partial build is kept, so the next run continues it",
+        timeout.as_secs() / 60
+    )
+}
+
 fn run_command(cmd: &mut Command, description: &str) -> Result<()> {
     let status = cmd
         .status()
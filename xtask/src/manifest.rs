@@ -0,0 +1,156 @@
+//! Manifest recording how a cached toolchain was built.
+//!
+//! Stored as plain `key = value` lines in the toolchain's cache directory so it
+//! can be read (and diffed) without any tooling.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+pub const MANIFEST_FILE: &str = "manifest";
+
+#[derive(Default)]
+pub struct Manifest {
+    fields: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Load the manifest in `dir`, or `None` if that toolchain has none yet.
+    pub fn load(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let fields = contents
+            .lines()
+            .filter_map(|line| line.split_once(" = "))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .collect();
+        Ok(Some(Self { fields }))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.get(key).map(String::as_str)
+    }
+
+    /// Fail if the toolchain in `dir` was built on a different platform, as
+    /// happens when a cache directory is shared between machines.
+    pub fn check_host(&self, dir: &Path) -> Result<()> {
+        let here = host();
+        match self.get("host") {
+            Some(built_on) if built_on != here => bail!(
+                "the toolchain in {} was built on {built_on}, but this machine is {here}; \
+                 its LLVM and linker binaries won't run here. Build one for this machine \
+                 with `cargo xtask setup`, under another `--toolchain` or `--cache-dir`",
+                dir.display()
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Set `key` in the manifest in `dir`, creating the manifest if needed.
+    pub fn update(dir: &Path, entries: &[(&str, String)]) -> Result<()> {
+        let mut manifest = Self::load(dir)?.unwrap_or_default();
+        for (key, value) in entries {
+            manifest.fields.insert(key.to_string(), value.clone());
+        }
+        let contents: String = manifest
+            .fields
+            .iter()
+            .map(|(key, value)| format!("{key} = {value}\n"))
+            .collect();
+        let path = dir.join(MANIFEST_FILE);
+        fs::write(&path, contents).with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// The commit checked out in the git repository at `dir`.
+pub fn git_head(dir: &Path) -> String {
+    command_output(
+        Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .current_dir(dir),
+    )
+    .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// The platform xtask runs on, e.g. `x86_64-linux`. The LLVM and linker
+/// binaries in a toolchain directory only run on the platform that built them.
+pub fn host() -> String {
+    format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+/// The branch checked out in the git repository at `dir`, if on one.
+pub fn git_branch(dir: &Path) -> Option<String> {
+    command_output(
+        Command::new("git")
+            .args(["rev-parse", "--abbrev-ref", "HEAD"])
+            .current_dir(dir),
+    )
+    .filter(|branch| branch != "HEAD")
+}
+
+/// Version reported by `llvm-config` in an LLVM install prefix.
+pub fn llvm_version(install_dir: &Path) -> String {
+    command_output(Command::new(install_dir.join("bin/llvm-config")).arg("--version"))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Version of the LLVM bundled with `rustc`, from the `LLVM version:` line
+/// of `rustc -vV`.
+pub fn rustc_llvm_version(toolchain: &str) -> Option<String> {
+    let output = command_output(Command::new("rustc").args([toolchain, "-vV"]))?;
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("LLVM version: "))
+        .map(|version| version.trim().to_string())
+}
+
+/// The major component of an LLVM version string such as `21.1.2`.
+pub fn llvm_major(version: &str) -> Option<u32> {
+    version.split('.').next()?.trim().parse().ok()
+}
+
+fn command_output(cmd: &mut Command) -> Option<String> {
+    cmd.output()
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+}
+
+/// Seconds since the Unix epoch, as recorded in `*_built_at` fields.
+pub fn now_unix() -> String {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Render a `*_built_at` value as a UTC `YYYY-MM-DD HH:MM` timestamp.
+pub fn format_unix(secs: &str) -> String {
+    let Ok(secs) = secs.parse::<i64>() else {
+        return secs.to_string();
+    };
+    // Civil-from-days conversion (Howard Hinnant's algorithm).
+    let days = secs.div_euclid(86400);
+    let time = secs.rem_euclid(86400);
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        time / 3600,
+        time % 3600 / 60
+    )
+}
@@ -0,0 +1,118 @@
+//! Project defaults for xtask options, read from `xtask.toml` in the project
+//! root so a team's toolchain configuration can be checked in. A flag given
+//! on the command line always wins over the file.
+
+use anyhow::{bail, Context, Result};
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const FILE_NAME: &str = "xtask.toml";
+/// Every key the file may set, named after the matching command-line flag.
+const KEYS: &[&str] = &["toolchain", "cache_dir", "stack_size", "target_cpu"];
+
+pub struct Settings {
+    path: PathBuf,
+    table: toml::Table,
+    /// `(key, value, source)` for each setting that wasn't left at its default
+    resolved: Vec<(String, String, String)>,
+}
+
+impl Settings {
+    /// Load `xtask.toml` from `project_root`. A missing file sets nothing;
+    /// an unknown key is an error, since it is almost always a typo.
+    pub fn load(project_root: &Path) -> Result<Self> {
+        let path = project_root.join(FILE_NAME);
+        let table = match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .parse::<toml::Table>()
+                .with_context(|| format!("failed to parse {}", path.display()))?,
+            Err(_) => toml::Table::default(),
+        };
+        if let Some(key) = table.keys().find(|key| !KEYS.contains(&key.as_str())) {
+            bail!(
+                "unknown setting `{key}` in {} (expected one of: {})",
+                path.display(),
+                KEYS.join(", ")
+            );
+        }
+        Ok(Self {
+            path,
+            table,
+            resolved: Vec::new(),
+        })
+    }
+
+    /// `cli` if the flag was given, otherwise the file's value for `key`.
+    pub fn resolve<T: FromSetting + Display>(
+        &mut self,
+        key: &str,
+        cli: Option<T>,
+    ) -> Result<Option<T>> {
+        let (value, source) = if let Some(value) = cli {
+            (value, "command line".to_string())
+        } else if let Some(value) = self.table.get(key) {
+            let value = T::from_setting(value).with_context(|| {
+                format!(
+                    "`{key}` in {} is not a valid {}",
+                    self.path.display(),
+                    T::KIND
+                )
+            })?;
+            (value, self.path.display().to_string())
+        } else {
+            return Ok(None);
+        };
+        self.resolved
+            .push((key.to_string(), value.to_string(), source));
+        Ok(Some(value))
+    }
+
+    /// Print where each non-default setting came from. Goes to stderr so it
+    /// never mixes with artifacts written to stdout.
+    pub fn print(&self) {
+        if self.resolved.is_empty() {
+            return;
+        }
+        eprintln!("Settings:");
+        for (key, value, source) in &self.resolved {
+            eprintln!("  {key} = {value} (from {source})");
+        }
+    }
+}
+
+/// A type a setting in `xtask.toml` can hold.
+pub trait FromSetting: Sized {
+    const KIND: &'static str;
+    fn from_setting(value: &toml::Value) -> Option<Self>;
+}
+
+impl FromSetting for String {
+    const KIND: &'static str = "string";
+    fn from_setting(value: &toml::Value) -> Option<Self> {
+        value.as_str().map(String::from)
+    }
+}
+
+impl FromSetting for u32 {
+    const KIND: &'static str = "non-negative integer";
+    fn from_setting(value: &toml::Value) -> Option<Self> {
+        value.as_integer()?.try_into().ok()
+    }
+}
+
+/// Paths print through `Display`, which `PathBuf` lacks.
+pub struct SettingPath(pub PathBuf);
+
+impl Display for SettingPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.display().fmt(f)
+    }
+}
+
+impl FromSetting for SettingPath {
+    const KIND: &'static str = "path";
+    fn from_setting(value: &toml::Value) -> Option<Self> {
+        value.as_str().map(|path| SettingPath(PathBuf::from(path)))
+    }
+}
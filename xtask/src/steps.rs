@@ -0,0 +1,116 @@
+//! Tracking of completed setup steps, persisted in the toolchain's cache
+//! directory so an interrupted setup can be resumed with `--resume`.
+//!
+//! With `--progress-json`, each step's transitions are also reported on
+//! stderr as one JSON object per line, for tools wrapping the setup:
+//! `{"step": "llvm-build", "status": "started", "elapsed_ms": 0}`. `status`
+//! is `started`, then `done` or `failed`, or just `skipped` for a step
+//! `--resume` skips; `elapsed_ms` is the time since the step started.
+
+use crate::build::json_string;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const MARKER_FILE: &str = "setup-progress";
+
+pub struct Steps {
+    marker: PathBuf,
+    done: Vec<String>,
+    resume: bool,
+    /// Report step transitions as JSON lines on stderr
+    progress_json: bool,
+    /// Wall-clock time of each step run (not skipped) so far
+    timings: Vec<(String, Duration)>,
+}
+
+impl Steps {
+    /// Load the steps recorded for the toolchain in `base_dir`. Unless
+    /// `resume` is set, recorded steps are only updated, never skipped.
+    pub fn load(base_dir: &Path, resume: bool, progress_json: bool) -> Result<Self> {
+        let marker = base_dir.join(MARKER_FILE);
+        let done = match fs::read_to_string(&marker) {
+            Ok(contents) => contents.lines().map(String::from).collect(),
+            Err(_) => Vec::new(),
+        };
+        Ok(Self {
+            marker,
+            done,
+            resume,
+            progress_json,
+            timings: Vec::new(),
+        })
+    }
+
+    /// Forget every recorded step, for a setup that starts from scratch.
+    pub fn reset(&mut self) -> Result<()> {
+        self.done.clear();
+        if self.marker.exists() {
+            fs::remove_file(&self.marker)
+                .with_context(|| format!("failed to remove {}", self.marker.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Run step `name`, unless resuming and it already completed, and record
+    /// it as completed once `f` succeeds.
+    pub fn run(&mut self, name: &str, f: impl FnOnce() -> Result<()>) -> Result<()> {
+        if self.resume && self.done.iter().any(|step| step == name) {
+            println!("  {name} already completed, skipping (--resume)");
+            self.report(name, "skipped", Duration::ZERO);
+            return Ok(());
+        }
+        self.report(name, "started", Duration::ZERO);
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+        if result.is_err() {
+            self.report(name, "failed", elapsed);
+        }
+        result?;
+        self.report(name, "done", elapsed);
+        println!("  {name} took {}", format_duration(elapsed));
+        self.timings.push((name.to_string(), elapsed));
+        if !self.done.iter().any(|step| step == name) {
+            self.done.push(name.to_string());
+        }
+        if let Some(parent) = self.marker.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents: String = self.done.iter().map(|step| format!("{step}\n")).collect();
+        fs::write(&self.marker, contents)
+            .with_context(|| format!("failed to write {}", self.marker.display()))
+    }
+
+    /// Report step `name` reaching `status` as a `--progress-json` line.
+    fn report(&self, name: &str, status: &str, elapsed: Duration) {
+        if self.progress_json {
+            eprintln!(
+                "{{\"step\": {}, \"status\": {}, \"elapsed_ms\": {}}}",
+                json_string(name),
+                json_string(status),
+                elapsed.as_millis()
+            );
+        }
+    }
+
+    /// Print how long each step took and the total.
+    pub fn print_timings(&self) {
+        println!("Step timings:");
+        for (name, elapsed) in &self.timings {
+            println!("  {name:<14} {:>10}", format_duration(*elapsed));
+        }
+        let total = self.timings.iter().map(|(_, elapsed)| *elapsed).sum();
+        println!("  {:<14} {:>10}", "total", format_duration(total));
+    }
+}
+
+pub fn format_duration(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..=59 => format!("{:.1}s", elapsed.as_secs_f64()),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}